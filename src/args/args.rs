@@ -16,4 +16,69 @@ pub struct Args {
     #[arg(short, long, value_hint = clap::ValueHint::DirPath, help = "Path to your Capti config file.",
         long_help = "By default, Capti will walk your tests directory (indicated by the --path argument) for a file named 'capti-config.yaml' or 'capti-config.yml'. If you wish to use a different file name, or specify a config located outside your tests directory, use this option.")]
     pub config: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Maximum number of suites or requests to run at once.",
+        long_help = "Limits how many test suites run concurrently, and how many test requests are in flight at once across every suite, including suites marked 'parallel: true'. Overrides the 'max_concurrency' setting in your config file. By default, there is no limit."
+    )]
+    pub jobs: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Refuse to run if any variable reference will not resolve.",
+        long_help = "Before running, Capti checks every suite for variables that are not defined by the suite, exported by a dependency, the environment, or the env file, and are not extracted by an earlier test. By default these are reported as warnings. With --strict, they are reported as errors and no tests are run."
+    )]
+    pub strict: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Name of the environment from your config file to run against.",
+        long_help = "Selects one of the named environments defined under 'environments' in your config file. The environment's env files, variables, base url, and client settings are layered over the rest of your configuration."
+    )]
+    pub env: Option<String>,
+
+    #[arg(
+        long = "var",
+        value_name = "KEY=VALUE",
+        value_parser = parse_var,
+        help = "Set a variable, overriding every other definition. May be repeated.",
+        long_help = "Sets a variable for every suite. Values passed with --var take precedence over --var-file, the selected environment, suite variables, test 'define' blocks, your shell environment, and your env files. May be repeated."
+    )]
+    pub vars: Vec<(String, String)>,
+
+    #[arg(
+        long = "var-file",
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Load variables from a YAML file. May be repeated.",
+        long_help = "Loads a YAML mapping of variables for every suite. Variable files take precedence over the selected environment, suite variables, test 'define' blocks, your shell environment, and your env files. When more than one file is given, later files take precedence."
+    )]
+    pub var_files: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Print every variable, its value, and where it came from, then exit.",
+        long_help = "Prints the final value of every variable used by each suite, and where that value came from, without running any tests. Secret values are masked."
+    )]
+    pub print_vars: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Keep running, and run suites again when their files change.",
+        long_help = "Runs every suite, then watches your tests directory and config files for changes. Only suites whose files changed are run again, while the setup scripts and Docker Compose services from your config file keep running between runs. Changes to your config file, or to the env, variable, or template files it uses, restart the setup and run every suite again. Press Ctrl-C to stop."
+    )]
+    pub watch: bool,
+}
+
+fn parse_var(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, found '{}'", arg)),
+    }
 }
@@ -1,33 +1,189 @@
-use std::ops::Deref;
+use std::{ops::Deref, sync::Arc};
+
+use reqwest::{
+    cookie::CookieStore,
+    header::{
+        AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION, PROXY_AUTHORIZATION,
+        WWW_AUTHENTICATE,
+    },
+    Method, Request, Response, StatusCode, Url,
+};
 
 use crate::errors::CaptiError;
 
+use super::{
+    client_config::{ClientConfig, RedirectPolicy},
+    cookie_jar::CookieJar,
+};
+
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
+/// Reqwest's default maximum number of redirects, used when redirects are followed manually.
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
 #[derive(Debug, Clone)]
-pub struct Client(reqwest::Client);
+pub struct Client {
+    client: reqwest::Client,
+    no_redirects: reqwest::Client,
+    max_redirects: usize,
+    cookies: Option<Arc<CookieJar>>,
+}
+
+impl Client {
+    /// Builds a client with the provided settings applied. A second client that never follows
+    /// redirects is built alongside it, sharing the same cookies, so that tests can inspect
+    /// redirect responses.
+    pub fn build(config: &ClientConfig) -> Result<Self, CaptiError> {
+        let cookies = match config.cookie_store {
+            Some(false) => None,
+            _ => Some(Arc::new(CookieJar::default())),
+        };
+
+        let prepared = config.prepare()?;
+
+        let client = prepared
+            .apply(Client::builder(&cookies))
+            .build()
+            .map_err(|e| CaptiError::ClientError { source: e })?;
+
+        let no_redirects = prepared
+            .apply(Client::builder(&cookies))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| CaptiError::ClientError { source: e })?;
+
+        // Tests that assert on redirects follow them even if the client doesn't
+        let max_redirects = match config.redirects {
+            Some(RedirectPolicy::Follow) | Some(RedirectPolicy::None) | None => {
+                DEFAULT_MAX_REDIRECTS
+            }
+            Some(RedirectPolicy::Limit(max)) => max,
+        };
+
+        Ok(Client {
+            client,
+            no_redirects,
+            max_redirects,
+            cookies,
+        })
+    }
+
+    fn builder(cookies: &Option<Arc<CookieJar>>) -> reqwest::ClientBuilder {
+        let builder = reqwest::Client::builder().user_agent(USER_AGENT);
+        match cookies {
+            Some(cookies) => builder.cookie_provider(cookies.clone()),
+            None => builder,
+        }
+    }
+
+    /// Removes every cookie stored by this client.
+    pub fn clear_cookies(&self) {
+        if let Some(cookies) = &self.cookies {
+            cookies.clear();
+        }
+    }
+
+    /// Returns the stored cookies that would be sent to the url, as `name=value` pairs.
+    pub fn cookies(&self, url: &Url) -> Vec<String> {
+        self.cookies
+            .as_ref()
+            .and_then(|cookies| cookies.cookies(url))
+            .and_then(|header| header.to_str().ok().map(String::from))
+            .map(|header| header.split("; ").map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// A client that returns redirect responses instead of following them.
+    pub fn without_redirects(&self) -> &reqwest::Client {
+        &self.no_redirects
+    }
+
+    /// Sends the request, following redirects manually so that each intermediate response can be
+    /// recorded. Returns the final response along with the status and `Location` header of every
+    /// redirect that was followed, or an error if the response is still a redirect after the
+    /// maximum number of redirects.
+    pub async fn send_recording_redirects(
+        &self,
+        request: Request,
+    ) -> Result<(Response, Vec<(u16, String)>), CaptiError> {
+        let mut redirects = Vec::new();
+        let mut request = request;
+
+        loop {
+            let next = request.try_clone();
+            let response = self.no_redirects.execute(request).await?;
+            let status = response.status();
+
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .map(String::from);
+
+            let (next, location) = match (next, location) {
+                (Some(next), Some(location)) if status.is_redirection() => (next, location),
+                _ => return Ok((response, redirects)),
+            };
+
+            if redirects.len() >= self.max_redirects {
+                return Err(CaptiError::redirect_error(format!(
+                    "Too many redirects, stopped after {} redirects at {}.",
+                    redirects.len(),
+                    response.url()
+                )));
+            }
+
+            let url = match response.url().join(&location) {
+                Ok(url) => url,
+                Err(_) => return Ok((response, redirects)),
+            };
+
+            redirects.push((status.as_u16(), location));
+            request = redirect_request(next, status, url);
+        }
+    }
+}
+
+/// Builds the request for the next hop of a redirect, following the same rules as reqwest: 303
+/// responses, and 301 or 302 responses to a POST, are followed with a GET and no body, and
+/// credentials and cookie headers are not sent to a different host.
+fn redirect_request(mut request: Request, status: StatusCode, url: Url) -> Request {
+    let switch_to_get = status == StatusCode::SEE_OTHER
+        || (matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+            && request.method() == Method::POST);
+
+    if switch_to_get {
+        *request.method_mut() = Method::GET;
+        *request.body_mut() = None;
+        request.headers_mut().remove(CONTENT_TYPE);
+        request.headers_mut().remove(CONTENT_LENGTH);
+    }
+
+    if request.url().host_str() != url.host_str() || request.url().port() != url.port() {
+        for header in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE] {
+            request.headers_mut().remove(header);
+        }
+    }
+
+    *request.url_mut() = url;
+    request
+}
 
 impl Default for Client {
     fn default() -> Self {
-        Client(
-            reqwest::Client::builder()
-                .user_agent(USER_AGENT)
-                .cookie_store(true)
-                .build()
-                .map_err(|e| {
-                    let error = CaptiError::ClientError { source: e };
-                    eprintln!("{}", error);
-                    error
-                })
-                .expect("Failed to start client."),
-        )
+        Client::build(&ClientConfig::default())
+            .map_err(|e| {
+                eprintln!("{}", e);
+                e
+            })
+            .expect("Failed to start client.")
     }
 }
 
 impl Deref for Client {
     type Target = reqwest::Client;
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.client
     }
 }
 
@@ -36,3 +192,61 @@ impl PartialEq for Client {
         false
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strips_credentials_on_cross_host_redirects() {
+        let url = Url::parse("http://localhost:3000/signin").unwrap();
+        let mut request = Request::new(Method::POST, url);
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, "Bearer token".parse().unwrap());
+        request
+            .headers_mut()
+            .insert(COOKIE, "session=abc".parse().unwrap());
+
+        let same_host = Url::parse("http://localhost:3000/dashboard").unwrap();
+        let request = redirect_request(request, StatusCode::SEE_OTHER, same_host);
+        assert_eq!(request.method(), Method::GET);
+        assert!(request.headers().contains_key(AUTHORIZATION));
+        assert!(request.headers().contains_key(COOKIE));
+
+        let other_host = Url::parse("http://example.com/dashboard").unwrap();
+        let request = redirect_request(request, StatusCode::FOUND, other_host);
+        assert!(!request.headers().contains_key(AUTHORIZATION));
+        assert!(!request.headers().contains_key(COOKIE));
+    }
+
+    #[tokio::test]
+    async fn errors_after_too_many_redirects() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            use std::io::{Read, Write};
+
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+            }
+        });
+
+        let config = ClientConfig {
+            redirects: Some(RedirectPolicy::Limit(2)),
+            ..ClientConfig::default()
+        };
+        let client = Client::build(&config).unwrap();
+        let url = format!("http://127.0.0.1:{}/loop", port);
+        let request = client.get(url).build().unwrap();
+
+        let result = client.send_recording_redirects(request).await;
+        assert!(matches!(result, Err(CaptiError::RedirectError(_))));
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::errors::CaptiError;
+
+/// Settings for the HTTP client used to send test requests. Settings can be defined in the config
+/// file and overridden by individual suites.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct ClientConfig {
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<ClientCertificate>,
+    pub accept_invalid_certs: Option<bool>,
+    pub proxy: Option<ProxyConfig>,
+    pub redirects: Option<RedirectPolicy>,
+    pub http_version: Option<HttpVersion>,
+    pub cookie_store: Option<bool>,
+}
+
+/// A client certificate used for mutual TLS, either as a PEM certificate with its PKCS #8 private
+/// key, or as a PKCS #12 archive.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ClientCertificate {
+    Pem {
+        cert: PathBuf,
+        key: PathBuf,
+    },
+    Pkcs12 {
+        pkcs12: PathBuf,
+        #[serde(default)]
+        password: String,
+    },
+}
+
+/// A proxy for all requests, or separate proxies for HTTP and HTTPS requests.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ProxyConfig {
+    All(String),
+    Scheme {
+        http: Option<String>,
+        https: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedirectPolicy {
+    Follow,
+    None,
+    Limit(usize),
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub enum HttpVersion {
+    #[serde(rename = "http1", alias = "1.1", alias = "http1.1")]
+    Http1,
+    #[serde(rename = "http2", alias = "2")]
+    Http2,
+}
+
+impl ClientConfig {
+    /// Returns a new config with each setting from `overrides` taking precedence over the settings
+    /// in this config.
+    pub fn merge(&self, overrides: &ClientConfig) -> ClientConfig {
+        ClientConfig {
+            ca_cert: overrides.ca_cert.clone().or(self.ca_cert.clone()),
+            client_cert: overrides.client_cert.clone().or(self.client_cert.clone()),
+            accept_invalid_certs: overrides.accept_invalid_certs.or(self.accept_invalid_certs),
+            proxy: overrides.proxy.clone().or(self.proxy.clone()),
+            redirects: overrides.redirects.clone().or(self.redirects.clone()),
+            http_version: overrides.http_version.clone().or(self.http_version.clone()),
+            cookie_store: overrides.cookie_store.or(self.cookie_store),
+        }
+    }
+
+    /// Resolves the certificate paths relative to the directory of the file that defines them.
+    pub fn resolve_paths(&mut self, base_dir: &Path) {
+        if let Some(ca_cert) = &mut self.ca_cert {
+            *ca_cert = base_dir.join(&ca_cert);
+        }
+
+        match &mut self.client_cert {
+            Some(ClientCertificate::Pem { cert, key }) => {
+                *cert = base_dir.join(&cert);
+                *key = base_dir.join(&key);
+            }
+            Some(ClientCertificate::Pkcs12 { pkcs12, .. }) => {
+                *pkcs12 = base_dir.join(&pkcs12);
+            }
+            None => {}
+        }
+    }
+
+    /// Reads any certificate files and parses any proxy URLs, validating that they are usable.
+    /// The result can be applied to any number of client builders.
+    pub fn prepare(&self) -> Result<PreparedClientConfig<'_>, CaptiError> {
+        let mut certificates = Vec::new();
+        if let Some(ca_cert) = &self.ca_cert {
+            let pem = read_file(ca_cert, "CA certificate")?;
+            certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| {
+                CaptiError::client_config_error(format!(
+                    "Invalid CA certificate bundle {:?}: {}",
+                    ca_cert, e
+                ))
+            })?;
+
+            if certificates.is_empty() {
+                return Err(CaptiError::client_config_error(format!(
+                    "No certificates found in CA certificate bundle {:?}.",
+                    ca_cert
+                )));
+            }
+        }
+
+        let identity = match &self.client_cert {
+            Some(ClientCertificate::Pem { cert, key }) => {
+                let cert_pem = read_file(cert, "client certificate")?;
+                let key_pem = read_file(key, "client certificate key")?;
+                let identity =
+                    reqwest::Identity::from_pkcs8_pem(&cert_pem, &key_pem).map_err(|e| {
+                        CaptiError::client_config_error(format!(
+                            "Invalid client certificate {:?} or key {:?}: {}",
+                            cert, key, e
+                        ))
+                    })?;
+                Some(identity)
+            }
+            Some(ClientCertificate::Pkcs12 { pkcs12, password }) => {
+                let der = read_file(pkcs12, "client certificate archive")?;
+                let identity = reqwest::Identity::from_pkcs12_der(&der, password).map_err(|e| {
+                    CaptiError::client_config_error(format!(
+                        "Invalid client certificate archive {:?}: {}",
+                        pkcs12, e
+                    ))
+                })?;
+                Some(identity)
+            }
+            None => None,
+        };
+
+        let mut proxies = Vec::new();
+        match &self.proxy {
+            Some(ProxyConfig::All(url)) => {
+                proxies.push(reqwest::Proxy::all(url).map_err(|e| invalid_proxy(url, e))?);
+            }
+            Some(ProxyConfig::Scheme { http, https }) => {
+                if let Some(url) = http {
+                    proxies.push(reqwest::Proxy::http(url).map_err(|e| invalid_proxy(url, e))?);
+                }
+                if let Some(url) = https {
+                    proxies.push(reqwest::Proxy::https(url).map_err(|e| invalid_proxy(url, e))?);
+                }
+            }
+            None => {}
+        }
+
+        Ok(PreparedClientConfig {
+            config: self,
+            certificates,
+            identity,
+            proxies,
+        })
+    }
+}
+
+/// Client settings whose certificate files have been read and proxy URLs parsed.
+pub struct PreparedClientConfig<'a> {
+    config: &'a ClientConfig,
+    certificates: Vec<reqwest::Certificate>,
+    identity: Option<reqwest::Identity>,
+    proxies: Vec<reqwest::Proxy>,
+}
+
+impl PreparedClientConfig<'_> {
+    /// Applies these settings to a reqwest client builder.
+    pub fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        for certificate in self.certificates.iter() {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+
+        if let Some(true) = self.config.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        for proxy in self.proxies.iter() {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        match &self.config.redirects {
+            Some(RedirectPolicy::Follow) | None => {}
+            Some(RedirectPolicy::None) => {
+                builder = builder.redirect(reqwest::redirect::Policy::none());
+            }
+            Some(RedirectPolicy::Limit(max)) => {
+                builder = builder.redirect(reqwest::redirect::Policy::limited(*max));
+            }
+        }
+
+        match &self.config.http_version {
+            Some(HttpVersion::Http1) => builder = builder.http1_only(),
+            Some(HttpVersion::Http2) => builder = builder.http2_prior_knowledge(),
+            None => {}
+        }
+
+        builder
+    }
+}
+
+fn read_file(path: &PathBuf, description: &str) -> Result<Vec<u8>, CaptiError> {
+    std::fs::read(path).map_err(|e| {
+        CaptiError::client_config_error(format!("Unable to read {} {:?}: {}", description, path, e))
+    })
+}
+
+fn invalid_proxy(url: &str, e: reqwest::Error) -> CaptiError {
+    CaptiError::client_config_error(format!("Invalid proxy URL {}: {}", url, e))
+}
+
+impl<'de> Deserialize<'de> for RedirectPolicy {
+    fn deserialize<D>(deserializer: D) -> Result<RedirectPolicy, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(RedirectPolicyVisitor)
+    }
+}
+
+struct RedirectPolicyVisitor;
+
+impl<'de> Visitor<'de> for RedirectPolicyVisitor {
+    type Value = RedirectPolicy;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("'follow', 'none', or a maximum number of redirects")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match value {
+            "follow" => Ok(RedirectPolicy::Follow),
+            "none" => Ok(RedirectPolicy::None),
+            _ => Err(E::custom(format!(
+                "Invalid redirect policy: {}\nMust be 'follow', 'none', or a maximum number of redirects.",
+                value
+            ))),
+        }
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match value {
+            true => Ok(RedirectPolicy::Follow),
+            false => Ok(RedirectPolicy::None),
+        }
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match value {
+            0 => Ok(RedirectPolicy::None),
+            max => Ok(RedirectPolicy::Limit(max as usize)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserializes_client_config() {
+        let yaml = r#"
+        accept_invalid_certs: true
+        proxy: http://localhost:8080
+        redirects: 3
+        http_version: "1.1"
+        "#;
+
+        let config = serde_yaml::from_str::<ClientConfig>(yaml).unwrap();
+
+        assert_eq!(config.accept_invalid_certs, Some(true));
+        assert_eq!(
+            config.proxy,
+            Some(ProxyConfig::All(String::from("http://localhost:8080")))
+        );
+        assert_eq!(config.redirects, Some(RedirectPolicy::Limit(3)));
+        assert_eq!(config.http_version, Some(HttpVersion::Http1));
+    }
+
+    #[test]
+    fn merges_overrides() {
+        let global =
+            serde_yaml::from_str::<ClientConfig>("redirects: none\nproxy: http://a").unwrap();
+        let suite = serde_yaml::from_str::<ClientConfig>("redirects: follow").unwrap();
+
+        let merged = global.merge(&suite);
+
+        assert_eq!(merged.redirects, Some(RedirectPolicy::Follow));
+        assert_eq!(
+            merged.proxy,
+            Some(ProxyConfig::All(String::from("http://a")))
+        );
+    }
+
+    #[test]
+    fn resolves_certificate_paths() {
+        let yaml = r#"
+        ca_cert: certs/ca.pem
+        client_cert:
+          pkcs12: /certs/client.p12
+        "#;
+
+        let mut config = serde_yaml::from_str::<ClientConfig>(yaml).unwrap();
+        config.resolve_paths(Path::new("tests"));
+
+        assert_eq!(config.ca_cert, Some(PathBuf::from("tests/certs/ca.pem")));
+        assert_eq!(
+            config.client_cert,
+            Some(ClientCertificate::Pkcs12 {
+                pkcs12: PathBuf::from("/certs/client.p12"),
+                password: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn errors_on_missing_certificate() {
+        let config = serde_yaml::from_str::<ClientConfig>("ca_cert: ./does-not-exist.pem").unwrap();
+
+        assert!(config.prepare().is_err());
+    }
+}
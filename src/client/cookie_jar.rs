@@ -0,0 +1,59 @@
+use std::sync::{Arc, RwLock};
+
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    header::HeaderValue,
+    Url,
+};
+
+/// A cookie store shared by the clients of a suite. Wraps reqwest's default cookie jar so that
+/// the stored cookies can be inspected and cleared between tests.
+#[derive(Debug, Default)]
+pub struct CookieJar(RwLock<Arc<Jar>>);
+
+impl CookieJar {
+    /// Removes every stored cookie.
+    pub fn clear(&self) {
+        if let Ok(mut jar) = self.0.write() {
+            *jar = Arc::new(Jar::default());
+        }
+    }
+
+    fn jar(&self) -> Arc<Jar> {
+        match self.0.read() {
+            Ok(jar) => jar.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        self.jar().set_cookies(cookie_headers, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.jar().cookies(url)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clears_stored_cookies() {
+        let jar = CookieJar::default();
+        let url = Url::parse("http://localhost:3000").unwrap();
+        let header = HeaderValue::from_static("session=abc; Path=/");
+
+        jar.set_cookies(&mut std::iter::once(&header), &url);
+        assert_eq!(
+            jar.cookies(&url),
+            Some(HeaderValue::from_static("session=abc"))
+        );
+
+        jar.clear();
+        assert_eq!(jar.cookies(&url), None);
+    }
+}
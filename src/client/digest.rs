@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use md5::{Digest, Md5};
+
+use crate::errors::CaptiError;
+
+/// A `WWW-Authenticate: Digest` challenge sent by a server, as described in RFC 7616. Only the
+/// MD5 algorithm is supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    qop: Option<String>,
+}
+
+impl DigestChallenge {
+    /// Parses a digest challenge from the value of a `WWW-Authenticate` header. Returns `None` if
+    /// the header is not a digest challenge.
+    pub fn parse(header: &str) -> Result<Option<Self>, CaptiError> {
+        let params = match header.trim().split_once(' ') {
+            Some((scheme, params)) if scheme.eq_ignore_ascii_case("digest") => params,
+            _ => return Ok(None),
+        };
+
+        let params = parse_params(params);
+
+        if let Some(algorithm) = params.get("algorithm") {
+            if !algorithm.eq_ignore_ascii_case("md5") {
+                return Err(CaptiError::auth_error(format!(
+                    "Unsupported digest algorithm: {}\nOnly MD5 is supported.",
+                    algorithm
+                )));
+            }
+        }
+
+        let nonce = params.get("nonce").cloned().ok_or(CaptiError::auth_error(
+            "Digest challenge is missing a nonce.",
+        ))?;
+
+        // Only the "auth" quality of protection is supported, which servers offering a choice
+        // will always include.
+        let qop = params.get("qop").and_then(|qop| {
+            qop.split(',')
+                .map(str::trim)
+                .find(|qop| *qop == "auth")
+                .map(String::from)
+        });
+
+        Ok(Some(DigestChallenge {
+            realm: params.get("realm").cloned().unwrap_or_default(),
+            nonce,
+            opaque: params.get("opaque").cloned(),
+            qop,
+        }))
+    }
+
+    /// Builds the `Authorization` header value answering this challenge.
+    pub fn authorization(&self, username: &str, password: &str, method: &str, uri: &str) -> String {
+        let cnonce = format!(
+            "{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos())
+                .unwrap_or_default()
+        );
+
+        self.authorization_with_cnonce(username, password, method, uri, &cnonce)
+    }
+
+    fn authorization_with_cnonce(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        cnonce: &str,
+    ) -> String {
+        let ha1 = md5_hex(&format!("{}:{}:{}", username, &self.realm, password));
+        let ha2 = md5_hex(&format!("{}:{}", method, uri));
+        let nc = "00000001";
+
+        let response = match &self.qop {
+            Some(qop) => md5_hex(&format!(
+                "{}:{}:{}:{}:{}:{}",
+                ha1, &self.nonce, nc, cnonce, qop, ha2
+            )),
+            None => md5_hex(&format!("{}:{}:{}", ha1, &self.nonce, ha2)),
+        };
+
+        let mut header = format!(
+            r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", response="{}""#,
+            username, &self.realm, &self.nonce, uri, response
+        );
+
+        if let Some(qop) = &self.qop {
+            header.push_str(&format!(r#", qop={}, nc={}, cnonce="{}""#, qop, nc, cnonce));
+        }
+
+        if let Some(opaque) = &self.opaque {
+            header.push_str(&format!(r#", opaque="{}""#, opaque));
+        }
+
+        header
+    }
+}
+
+/// Splits comma separated `key=value` parameters, where values may be quoted and contain commas.
+fn parse_params(params: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut rest = params.trim();
+
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let after = after.trim_start();
+
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => match after.find(',') {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, ""),
+            },
+        };
+
+        result.insert(key, value.trim().to_string());
+        rest = remaining.trim_start().trim_start_matches(',');
+    }
+
+    result
+}
+
+fn md5_hex(value: &str) -> String {
+    Md5::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn answers_digest_challenge() {
+        let challenge = DigestChallenge::parse(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .unwrap()
+        .unwrap();
+
+        let header = challenge.authorization_with_cnonce(
+            "Mufasa",
+            "Circle Of Life",
+            "GET",
+            "/dir/index.html",
+            "0a4f113b",
+        );
+
+        assert!(header.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(header.contains("qop=auth, nc=00000001"));
+        assert!(header.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+    }
+
+    #[test]
+    fn ignores_other_schemes() {
+        assert_eq!(
+            DigestChallenge::parse(r#"Basic realm="api""#).unwrap(),
+            None
+        );
+    }
+}
@@ -1,3 +1,9 @@
 pub mod client;
+pub mod client_config;
+pub mod cookie_jar;
+pub mod digest;
+pub mod oauth2;
+pub mod signing;
 
 pub use client::Client;
+pub use client_config::ClientConfig;
@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::{
+    errors::CaptiError,
+    runner::run_config::RunConfig,
+    variables::{secrets, variable_map::VariableMap},
+};
+
+/// Tokens are refreshed this long before they expire, so they don't expire mid-request. Tokens
+/// that last less than twice this long are refreshed halfway through their lifetime instead.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Tokens fetched during this run, keyed by provider name. Holding the lock while fetching makes
+/// concurrent suites share a single token request per provider.
+static TOKENS: Lazy<Mutex<HashMap<String, OAuth2Token>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GrantType {
+    ClientCredentials,
+    Password,
+}
+
+impl GrantType {
+    fn name(&self) -> &'static str {
+        match self {
+            GrantType::ClientCredentials => "client_credentials",
+            GrantType::Password => "password",
+        }
+    }
+}
+
+/// An OAuth2 token endpoint declared in the config file. Values may reference environment
+/// variables.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OAuth2Provider {
+    pub token_url: String,
+    pub grant_type: GrantType,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct OAuth2Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    /// When the token should be refreshed, a little before it expires.
+    refresh_at: Option<Instant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+impl OAuth2Token {
+    fn is_expired(&self) -> bool {
+        self.refresh_at
+            .is_some_and(|refresh_at| Instant::now() >= refresh_at)
+    }
+}
+
+impl From<TokenResponse> for OAuth2Token {
+    fn from(response: TokenResponse) -> Self {
+        OAuth2Token {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            refresh_at: response.expires_in.map(|seconds| {
+                let lifetime = Duration::from_secs(seconds);
+                Instant::now() + lifetime - EXPIRY_MARGIN.min(lifetime / 2)
+            }),
+        }
+    }
+}
+
+/// Returns an access token for the named provider, fetching a new token the first time it is
+/// requested and whenever the cached token has expired.
+pub async fn access_token(provider: &str, client: &reqwest::Client) -> Result<String, CaptiError> {
+    let config =
+        RunConfig::global()
+            .oauth2
+            .get(provider)
+            .cloned()
+            .ok_or(CaptiError::auth_error(format!(
+                "OAuth2 provider '{}' is not defined in the config file.",
+                provider
+            )))?;
+
+    let mut tokens = TOKENS.lock().await;
+
+    let token = match tokens.get(provider) {
+        Some(token) if !token.is_expired() => return Ok(token.access_token.clone()),
+        Some(OAuth2Token {
+            refresh_token: Some(refresh_token),
+            ..
+        }) => match config.refresh(refresh_token, client).await {
+            Ok(token) => token,
+            Err(_) => config.fetch(client).await?,
+        },
+        _ => config.fetch(client).await?,
+    };
+
+    let access_token = token.access_token.clone();
+    tokens.insert(provider.to_string(), token);
+
+    Ok(access_token)
+}
+
+impl OAuth2Provider {
+    async fn fetch(&self, client: &reqwest::Client) -> Result<OAuth2Token, CaptiError> {
+        let mut form = match self.grant_type {
+            GrantType::ClientCredentials => {
+                vec![("grant_type", String::from(self.grant_type.name()))]
+            }
+            GrantType::Password => vec![
+                ("grant_type", String::from(self.grant_type.name())),
+                (
+                    "username",
+                    self.resolve(self.username.as_ref(), "username")?,
+                ),
+                (
+                    "password",
+                    self.resolve_secret(self.password.as_ref(), "password")?,
+                ),
+            ],
+        };
+
+        if let Some(scope) = &self.scope {
+            form.push(("scope", self.resolve(Some(scope), "scope")?));
+        }
+
+        self.request_token(form, client).await
+    }
+
+    async fn refresh(
+        &self,
+        refresh_token: &str,
+        client: &reqwest::Client,
+    ) -> Result<OAuth2Token, CaptiError> {
+        let form = vec![
+            ("grant_type", String::from("refresh_token")),
+            ("refresh_token", refresh_token.to_string()),
+        ];
+
+        self.request_token(form, client).await
+    }
+
+    async fn request_token(
+        &self,
+        mut form: Vec<(&str, String)>,
+        client: &reqwest::Client,
+    ) -> Result<OAuth2Token, CaptiError> {
+        let token_url = self.resolve(Some(&self.token_url), "token_url")?;
+        form.push((
+            "client_id",
+            self.resolve(Some(&self.client_id), "client_id")?,
+        ));
+        if let Some(client_secret) = &self.client_secret {
+            let client_secret = self.resolve(Some(client_secret), "client_secret")?;
+            secrets::register(&client_secret);
+            form.push(("client_secret", client_secret));
+        }
+
+        let response = client.post(&token_url).form(&form).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(CaptiError::auth_error(format!(
+                "Token request to {} failed with status {}:\n  {}",
+                &token_url, status, body
+            )));
+        }
+
+        let token = response.json::<TokenResponse>().await.map_err(|e| {
+            CaptiError::auth_error(format!("Invalid token response from {}: {}", &token_url, e))
+        })?;
+
+        secrets::register(&token.access_token);
+        if let Some(refresh_token) = &token.refresh_token {
+            secrets::register(refresh_token);
+        }
+
+        Ok(token.into())
+    }
+
+    fn resolve(&self, value: Option<&String>, field: &str) -> Result<String, CaptiError> {
+        let value = value.ok_or(CaptiError::auth_error(format!(
+            "OAuth2 {} grant requires a {}.",
+            self.grant_type.name(),
+            field
+        )))?;
+
+        Ok(VariableMap::new().replace_variables(value.as_str())?.into())
+    }
+
+    /// Resolves a value that must be masked in output.
+    fn resolve_secret(&self, value: Option<&String>, field: &str) -> Result<String, CaptiError> {
+        let value = self.resolve(value, field)?;
+        secrets::register(&value);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expires_tokens_early() {
+        let token = OAuth2Token::from(TokenResponse {
+            access_token: String::from("abc"),
+            refresh_token: None,
+            expires_in: Some(0),
+        });
+        assert!(token.is_expired());
+
+        let token = OAuth2Token::from(TokenResponse {
+            access_token: String::from("abc"),
+            refresh_token: None,
+            expires_in: Some(10),
+        });
+        assert!(!token.is_expired());
+
+        let token = OAuth2Token::from(TokenResponse {
+            access_token: String::from("abc"),
+            refresh_token: None,
+            expires_in: Some(3600),
+        });
+        assert!(!token.is_expired());
+
+        let token = OAuth2Token::from(TokenResponse {
+            access_token: String::from("abc"),
+            refresh_token: None,
+            expires_in: None,
+        });
+        assert!(!token.is_expired());
+    }
+}
@@ -0,0 +1,355 @@
+use base64::Engine;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION},
+    Request, Url,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::errors::CaptiError;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+pub struct AwsCredentials<'a> {
+    pub access_key: &'a str,
+    pub secret_key: &'a str,
+    pub session_token: Option<&'a str>,
+}
+
+/// Builds the string signed by HMAC signing: the method, path, canonical query string, the
+/// timestamp if one is sent, each signed header as `name:value`, and the SHA-256 hash of the
+/// body, separated by newlines.
+pub fn hmac_string_to_sign(
+    request: &Request,
+    signed_headers: &[String],
+    timestamp: Option<&str>,
+) -> String {
+    let mut lines = vec![
+        request.method().to_string(),
+        request.url().path().to_string(),
+        canonical_query(request.url()),
+    ];
+
+    if let Some(timestamp) = timestamp {
+        lines.push(timestamp.to_string());
+    }
+
+    for header in signed_headers {
+        let value = request
+            .headers()
+            .get(header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        lines.push(format!("{}:{}", header.to_lowercase(), value.trim()));
+    }
+
+    lines.push(sha256_hex(body_bytes(request)));
+
+    lines.join("\n")
+}
+
+/// Signs the string with the secret and encodes the signature.
+pub fn hmac_signature(
+    secret: &str,
+    string_to_sign: &str,
+    algorithm: &HmacAlgorithm,
+    encoding: &SignatureEncoding,
+) -> Result<String, CaptiError> {
+    let signature = match algorithm {
+        HmacAlgorithm::Sha256 => {
+            hmac::<Hmac<Sha256>>(secret.as_bytes(), string_to_sign.as_bytes())?
+        }
+        HmacAlgorithm::Sha512 => {
+            hmac::<Hmac<Sha512>>(secret.as_bytes(), string_to_sign.as_bytes())?
+        }
+    };
+
+    Ok(match encoding {
+        SignatureEncoding::Hex => hex(&signature),
+        SignatureEncoding::Base64 => base64::engine::general_purpose::STANDARD.encode(signature),
+    })
+}
+
+/// Signs the request with AWS Signature Version 4, adding the `Authorization`, `X-Amz-Date`, and
+/// any session token headers. S3 requests also include the `X-Amz-Content-Sha256` header.
+pub fn sign_aws_sigv4(
+    request: &mut Request,
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    now: OffsetDateTime,
+) -> Result<(), CaptiError> {
+    let amz_date = now
+        .format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))
+        .map_err(|e| CaptiError::auth_error(format!("Unable to format request date: {}", e)))?;
+    let date = &amz_date[..8];
+    let payload_hash = sha256_hex(body_bytes(request));
+
+    insert_header(request, "x-amz-date", &amz_date)?;
+    if service == "s3" {
+        insert_header(request, "x-amz-content-sha256", &payload_hash)?;
+    }
+    if let Some(session_token) = credentials.session_token {
+        insert_header(request, "x-amz-security-token", session_token)?;
+    }
+
+    let host = match (request.url().host_str(), request.url().port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => String::new(),
+    };
+
+    let mut headers = vec![(String::from("host"), host)];
+    for (name, value) in request.headers().iter() {
+        let name = name.as_str().to_lowercase();
+        if name.starts_with("x-amz-") || name == "content-type" {
+            headers.push((name, value.to_str().unwrap_or_default().trim().to_string()));
+        }
+    }
+    headers.sort();
+
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect::<String>();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<&str>>()
+        .join(";");
+
+    // Services other than S3 expect each segment of the already encoded path to be encoded again
+    let path = match (request.url().path(), service) {
+        ("", _) => String::from("/"),
+        (path, "s3") => path.to_string(),
+        (path, _) => path
+            .split('/')
+            .map(uri_encode)
+            .collect::<Vec<String>>()
+            .join("/"),
+    };
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method(),
+        path,
+        canonical_query(request.url()),
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let mut key = hmac::<Hmac<Sha256>>(
+        format!("AWS4{}", credentials.secret_key).as_bytes(),
+        date.as_bytes(),
+    )?;
+    for part in [region, service, "aws4_request"] {
+        key = hmac::<Hmac<Sha256>>(&key, part.as_bytes())?;
+    }
+    let signature = hex(&hmac::<Hmac<Sha256>>(&key, string_to_sign.as_bytes())?);
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key, scope, signed_headers, signature
+    );
+    insert_header(request, AUTHORIZATION.as_str(), &authorization)
+}
+
+/// Sets a header on the request, replacing any existing value.
+pub fn insert_header(request: &mut Request, name: &str, value: &str) -> Result<(), CaptiError> {
+    let header_name = HeaderName::from_bytes(name.as_bytes());
+    let header_value = HeaderValue::from_str(value);
+
+    match (header_name, header_value) {
+        (Ok(name), Ok(value)) => {
+            request.headers_mut().insert(name, value);
+            Ok(())
+        }
+        _ => Err(CaptiError::HTTPHeaderError(format!(
+            "Invalid HTTP header:\n  {}: {}",
+            name, value
+        ))),
+    }
+}
+
+/// The query parameters sorted by name and then value, with each name and value URI encoded.
+fn canonical_query(url: &Url) -> String {
+    let mut params = url
+        .query_pairs()
+        .map(|(name, value)| (uri_encode(&name), uri_encode(&value)))
+        .collect::<Vec<(String, String)>>();
+    params.sort();
+
+    params
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Percent encodes every character except the unreserved characters of RFC 3986.
+pub(crate) fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn body_bytes(request: &Request) -> &[u8] {
+    request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hmac<M>(key: &[u8], data: &[u8]) -> Result<Vec<u8>, CaptiError>
+where
+    M: Mac + KeyInit,
+{
+    let mut mac = <M as KeyInit>::new_from_slice(key)
+        .map_err(|e| CaptiError::auth_error(format!("Invalid signing key: {}", e)))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Method;
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn signs_aws_sigv4_test_vector() {
+        // The "get-vanilla" case from the AWS Signature Version 4 test suite.
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let mut request = Request::new(Method::GET, url);
+        let credentials = AwsCredentials {
+            access_key: "AKIDEXAMPLE",
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            session_token: None,
+        };
+
+        sign_aws_sigv4(
+            &mut request,
+            &credentials,
+            "us-east-1",
+            "service",
+            datetime!(2015-08-30 12:36:00 UTC),
+        )
+        .unwrap();
+
+        assert_eq!(
+            request.headers()[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+    }
+
+    #[test]
+    fn encodes_aws_sigv4_paths_twice() {
+        let url = Url::parse("https://example.amazonaws.com/documents and settings/").unwrap();
+        let mut request = Request::new(Method::GET, url);
+        let credentials = AwsCredentials {
+            access_key: "AKIDEXAMPLE",
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            session_token: None,
+        };
+
+        sign_aws_sigv4(
+            &mut request,
+            &credentials,
+            "us-east-1",
+            "service",
+            datetime!(2015-08-30 12:36:00 UTC),
+        )
+        .unwrap();
+
+        // Signed with the canonical path "/documents%2520and%2520settings/"
+        assert_eq!(
+            request.headers()[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=23c9727f014f850a592311a0323b422f9c1e3ad2d406c610f00d64ab3272c75a"
+        );
+    }
+
+    #[test]
+    fn builds_hmac_string_to_sign() {
+        let url = Url::parse("http://localhost:3000/orders?b=2&a=hello world").unwrap();
+        let mut request = Request::new(Method::POST, url);
+        insert_header(&mut request, "Content-Type", "application/json").unwrap();
+
+        let string_to_sign = hmac_string_to_sign(
+            &request,
+            &[String::from("Content-Type")],
+            Some("1700000000"),
+        );
+
+        assert_eq!(
+            string_to_sign,
+            "POST\n/orders\na=hello%20world&b=2\n1700000000\ncontent-type:application/json\ne3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn encodes_hmac_signatures() {
+        let hex = hmac_signature(
+            "key",
+            "The quick brown fox jumps over the lazy dog",
+            &HmacAlgorithm::Sha256,
+            &SignatureEncoding::Hex,
+        )
+        .unwrap();
+        assert_eq!(
+            hex,
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+
+        let base64 = hmac_signature(
+            "key",
+            "The quick brown fox jumps over the lazy dog",
+            &HmacAlgorithm::Sha256,
+            &SignatureEncoding::Base64,
+        )
+        .unwrap();
+        assert_eq!(base64, "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg=");
+    }
+}
@@ -36,12 +36,18 @@ pub enum CaptiError {
     #[error("Extraction from response failed: {0}")]
     ExtractError(String),
 
-    #[error("Error occurred attempting to run tests in parallel: {0}")]
-    ParallelError(String),
+    #[error("Error occurred following redirects: {0}")]
+    RedirectError(String),
 
     #[error("Error occurred setting up client for requests. Error: {source}")]
     ClientError { source: reqwest::Error },
 
+    #[error("Invalid client configuration: {0}")]
+    ClientConfigError(String),
+
+    #[error("Error occurred authenticating request: {0}")]
+    AuthError(String),
+
     #[error("Error occurred parsing or setting suite variables: {0}")]
     VariableError(String),
 
@@ -50,6 +56,27 @@ pub enum CaptiError {
 
     #[error("Matcher error occurred:\n{message}\n ")]
     MatcherError { message: String },
+
+    #[error("Error occurred loading test data: {0}")]
+    TestDataError(String),
+
+    #[error("Error occurred applying test templates: {0}")]
+    TemplateError(String),
+
+    #[error("Error occurred including suite tests: {0}")]
+    IncludeError(String),
+
+    #[error("Suite dependency could not be satisfied: {0}")]
+    DependencyError(String),
+
+    #[error("Invalid configuration file: {0}")]
+    ConfigError(String),
+
+    #[error("Unable to select environment: {0}")]
+    EnvironmentError(String),
+
+    #[error("Setup failed: {0}")]
+    SetupError(String),
 }
 
 impl CaptiError {
@@ -57,8 +84,48 @@ impl CaptiError {
         CaptiError::ExtractError(message.into())
     }
 
-    pub fn parallel_error(message: impl Into<String>) -> Self {
-        CaptiError::ParallelError(message.into())
+    pub fn redirect_error(message: impl Into<String>) -> Self {
+        CaptiError::RedirectError(message.into())
+    }
+
+    pub fn client_config_error(message: impl Into<String>) -> Self {
+        CaptiError::ClientConfigError(message.into())
+    }
+
+    pub fn auth_error(message: impl Into<String>) -> Self {
+        CaptiError::AuthError(message.into())
+    }
+
+    pub fn variable_error(message: impl Into<String>) -> Self {
+        CaptiError::VariableError(message.into())
+    }
+
+    pub fn test_data_error(message: impl Into<String>) -> Self {
+        CaptiError::TestDataError(message.into())
+    }
+
+    pub fn template_error(message: impl Into<String>) -> Self {
+        CaptiError::TemplateError(message.into())
+    }
+
+    pub fn include_error(message: impl Into<String>) -> Self {
+        CaptiError::IncludeError(message.into())
+    }
+
+    pub fn dependency_error(message: impl Into<String>) -> Self {
+        CaptiError::DependencyError(message.into())
+    }
+
+    pub fn config_error(message: impl Into<String>) -> Self {
+        CaptiError::ConfigError(message.into())
+    }
+
+    pub fn environment_error(message: impl Into<String>) -> Self {
+        CaptiError::EnvironmentError(message.into())
+    }
+
+    pub fn setup_error(message: impl Into<String>) -> Self {
+        CaptiError::SetupError(message.into())
     }
 
     pub fn matcher_error(message: impl Into<String>) -> Self {
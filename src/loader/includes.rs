@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use serde_yaml::Value;
+
+use crate::errors::CaptiError;
+
+/// Resolves the `include` field of a suite by prepending the tests of each included suite file,
+/// in order, to the suite's own tests. Included paths are relative to the including suite file,
+/// and included suites may include other suites as long as no cycle is formed.
+pub fn resolve_includes(
+    suite: &mut Value,
+    path: &Path,
+    visited: &mut Vec<PathBuf>,
+) -> Result<(), CaptiError> {
+    let mapping = match suite.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => return Ok(()),
+    };
+
+    let includes = match mapping.remove("include") {
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Sequence(includes)) => includes
+            .into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(include),
+                other => Err(CaptiError::include_error(format!(
+                    "Invalid include path: {:?}\nIncluded suites must be file paths.",
+                    other
+                ))),
+            })
+            .collect::<Result<Vec<String>, CaptiError>>()?,
+        Some(other) => {
+            return Err(CaptiError::include_error(format!(
+                "Invalid value for include: {:?}\nMust be a file path or a list of file paths.",
+                other
+            )))
+        }
+        None => return Ok(()),
+    };
+
+    let canonical_path = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    if visited.contains(&canonical_path) {
+        return Err(CaptiError::include_error(format!(
+            "Circular include detected for suite {:?}.",
+            path
+        )));
+    }
+    visited.push(canonical_path);
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut tests = Vec::new();
+
+    for include in includes {
+        let include_path = base_dir.join(&include);
+        let contents = std::fs::read_to_string(&include_path).map_err(|e| {
+            CaptiError::include_error(format!(
+                "Unable to read included suite {:?}: {}",
+                &include_path, e
+            ))
+        })?;
+
+        let mut included = serde_yaml::from_str::<Value>(&contents)?;
+        resolve_includes(&mut included, &include_path, visited)?;
+
+        let include_dir = std::fs::canonicalize(&include_path)
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf));
+
+        if let Some(Value::Sequence(included_tests)) = included.get("tests") {
+            for test in included_tests.iter() {
+                let mut test = test.clone();
+                if let Some(include_dir) = &include_dir {
+                    rebase_test_data(&mut test, include_dir);
+                }
+                tests.push(test);
+            }
+        }
+    }
+
+    visited.pop();
+
+    let mapping = match suite.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => return Ok(()),
+    };
+
+    if let Some(Value::Sequence(own_tests)) = mapping.get("tests") {
+        tests.extend(own_tests.iter().cloned());
+    }
+    mapping.insert(Value::from("tests"), Value::Sequence(tests));
+
+    Ok(())
+}
+
+/// Returns the canonical paths of the suite files that a suite file includes, directly or through
+/// other included suites. Files that can't be read or parsed are skipped.
+pub fn included_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_included_files(path, &mut files);
+    files
+}
+
+fn collect_included_files(path: &Path, files: &mut Vec<PathBuf>) {
+    let suite = match std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_yaml::from_str::<Value>(&contents).ok())
+    {
+        Some(suite) => suite,
+        None => return,
+    };
+
+    let includes = match suite.get("include") {
+        Some(Value::String(include)) => vec![include.clone()],
+        Some(Value::Sequence(includes)) => includes
+            .iter()
+            .filter_map(|include| include.as_str().map(String::from))
+            .collect(),
+        _ => return,
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        let include_path = base_dir.join(include);
+        let canonical_path = std::fs::canonicalize(&include_path).unwrap_or(include_path);
+        if files.contains(&canonical_path) {
+            continue;
+        }
+
+        files.push(canonical_path.clone());
+        collect_included_files(&canonical_path, files);
+    }
+}
+
+/// Test data file paths are relative to the suite that defines them, so included tests have their
+/// data paths made absolute before they are moved into another suite.
+fn rebase_test_data(test: &mut Value, include_dir: &Path) {
+    for key in ["each", "matrix"] {
+        if let Some(Value::String(data_path)) = test.get_mut(key) {
+            *data_path = include_dir.join(&data_path).to_string_lossy().to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rebases_test_data_paths() {
+        let mut test = serde_yaml::from_str::<Value>("each: data/users.csv").unwrap();
+        rebase_test_data(&mut test, Path::new("/tests/shared"));
+
+        assert_eq!(test["each"], Value::from("/tests/shared/data/users.csv"));
+    }
+}
@@ -1 +1,3 @@
+pub(crate) mod includes;
 pub mod suite_loader;
+pub mod templates;
@@ -1,9 +1,18 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use colored::Colorize;
+use serde_yaml::Value;
 use walkdir::WalkDir;
 
-use crate::{formatting::indent::Indent, progress_println, runner::run_config::RunConfig, Suite};
+use crate::{
+    errors::CaptiError,
+    formatting::indent::Indent,
+    progress_println,
+    runner::run_config::{ConfigOptions, RunConfig},
+    Suite,
+};
+
+use super::includes::resolve_includes;
 
 pub struct SuiteLoader<'a> {
     path: &'a PathBuf,
@@ -15,7 +24,20 @@ impl<'a> SuiteLoader<'a> {
     }
 
     pub fn load_suites(&self) -> Vec<Suite> {
-        let suites = WalkDir::new(&self.path)
+        let suites = self
+            .suite_files()
+            .iter()
+            .filter_map(|path| SuiteLoader::load_suite(path))
+            .collect::<Vec<Suite>>();
+
+        progress_println!("Found and loaded {} test suites.", suites.len());
+
+        suites
+    }
+
+    /// Returns the paths of the YAML files in the tests directory, other than the config file.
+    pub fn suite_files(&self) -> Vec<PathBuf> {
+        WalkDir::new(self.path)
             .into_iter()
             .filter_map(|e| e.ok())
             .filter(|e| {
@@ -24,30 +46,61 @@ impl<'a> SuiteLoader<'a> {
             })
             .filter(|e| e.file_name() != "capti-config.yaml" && e.file_name() != "capti-config.yml")
             .map(|e| e.path().to_path_buf())
-            .filter_map(|path| {
-                std::fs::read_to_string(&path)
-                    .map_err(|e| {
-                        progress_println!("{}: The file {:?} could not be read as a Capti test. Please confirm the file contains valid UTF-8 encoding.\n{}", "WARN".yellow(), &path, e.to_string().indent());
-                        e
-                    }).ok().map(|data| (data, path))
-            })
-            .filter_map(|(data, path)| {
-                serde_yaml::from_str::<Suite>(&data)
-                    .map_err(|e| {
-                        eprintln!("Failed to parse suite: {}", e);
-                        progress_println!("{}: The file {:?} exists in the specified path for Capti tests, but could not be parsed as a Capti test.\n Please confirm the file contains valid YAML structure and Capti fields.\n{}", "WARN".yellow(), &path, e.to_string().indent());
-                        e
-                    })
-                    .ok()
+            .collect()
+    }
+
+    /// Reads and parses a single suite file. Files that can't be loaded are reported as warnings.
+    pub fn load_suite(path: &Path) -> Option<Suite> {
+        let data = std::fs::read_to_string(path)
+            .inspect_err(|e| {
+                progress_println!("{}: The file {:?} could not be read as a Capti test. Please confirm the file contains valid UTF-8 encoding.\n{}", "WARN".yellow(), &path, e.to_string().indent());
             })
-            .collect::<Vec<Suite>>();
+            .ok()?;
 
-        progress_println!("Found and loaded {} test suites.", suites.len());
+        match SuiteLoader::parse_suite(&data, path) {
+            Ok(suite) => Some(suite),
+            Err(CaptiError::YamlParseError { source: e }) => {
+                eprintln!("Failed to parse suite: {}", e);
+                progress_println!("{}: The file {:?} exists in the specified path for Capti tests, but could not be parsed as a Capti test.\n Please confirm the file contains valid YAML structure and Capti fields.\n{}", "WARN".yellow(), &path, e.to_string().indent());
+                None
+            }
+            Err(e) => {
+                progress_println!(
+                    "{}: The suite {:?} could not be loaded.\n{}",
+                    "WARN".yellow(),
+                    &path,
+                    e.to_string().indent()
+                );
+                None
+            }
+        }
+    }
 
-        suites
+    /// Parses the contents of a suite file, resolving any included suites and test templates
+    /// before deserializing, and then expanding any data-driven tests.
+    pub fn parse_suite(data: &str, path: &Path) -> Result<Suite, CaptiError> {
+        let mut value = serde_yaml::from_str::<Value>(data)?;
+
+        resolve_includes(&mut value, path, &mut Vec::new())?;
+
+        if let Some(Value::Sequence(tests)) = value.get_mut("tests") {
+            let templates = &RunConfig::global().templates;
+            for test in tests.iter_mut() {
+                templates.apply(test)?;
+            }
+        }
+
+        let mut suite = serde_yaml::from_value::<Suite>(value)?;
+        suite.override_variables(&RunConfig::global().variables);
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        suite.configure_client(base_dir)?;
+        suite.expand_tests(base_dir)?;
+
+        Ok(suite)
     }
 
-    pub fn load_config(&self, config_path: &Option<PathBuf>) {
+    pub fn load_config(&self, config_path: &Option<PathBuf>, options: ConfigOptions) {
         let config_path = match config_path {
             Some(path) => Some(path.clone()),
             None => WalkDir::new(&self.path)
@@ -60,6 +113,6 @@ impl<'a> SuiteLoader<'a> {
                 .map(|e| e.path().to_path_buf())
                 .next(),
         };
-        RunConfig::load(config_path);
+        RunConfig::load(config_path, options);
     }
 }
@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::errors::CaptiError;
+
+/// Named test fragments that can be reused across suites. A template is any partial test
+/// definition, typically a `request` and/or `expect` mapping, which tests can inherit from with
+/// `extends`. Templates are defined in the config file or in shared template files.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Templates(Mapping);
+
+impl Templates {
+    /// Loads each template file, resolved relative to `base_dir`, and adds its templates. Templates
+    /// already defined take precedence over templates with the same name in a file.
+    pub fn load_files(&mut self, files: &[PathBuf], base_dir: &Path) -> Result<(), CaptiError> {
+        for file in files {
+            let path = base_dir.join(file);
+            let contents = std::fs::read_to_string(&path).map_err(|e| {
+                CaptiError::template_error(format!(
+                    "Unable to read template file {:?}: {}",
+                    &path, e
+                ))
+            })?;
+
+            let templates = serde_yaml::from_str::<Templates>(&contents).map_err(|e| {
+                CaptiError::template_error(format!(
+                    "Unable to parse template file {:?}: {}",
+                    &path, e
+                ))
+            })?;
+
+            for (name, template) in templates.0 {
+                if !self.0.contains_key(&name) {
+                    self.0.insert(name, template);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the templates named in the test's `extends` field, in order, and then merges the
+    /// test's own fields over the result.
+    pub fn apply(&self, test: &mut Value) -> Result<(), CaptiError> {
+        let mapping = match test.as_mapping_mut() {
+            Some(mapping) => mapping,
+            None => return Ok(()),
+        };
+
+        let names = match mapping.remove("extends") {
+            Some(Value::String(name)) => vec![name],
+            Some(Value::Sequence(names)) => names
+                .into_iter()
+                .map(|name| match name {
+                    Value::String(name) => Ok(name),
+                    other => Err(CaptiError::template_error(format!(
+                        "Invalid template name: {:?}\nTemplate names must be strings.",
+                        other
+                    ))),
+                })
+                .collect::<Result<Vec<String>, CaptiError>>()?,
+            Some(other) => {
+                return Err(CaptiError::template_error(format!(
+                    "Invalid value for extends: {:?}\nMust be a template name or a list of template names.",
+                    other
+                )))
+            }
+            None => return Ok(()),
+        };
+
+        let mut result = Value::Mapping(Default::default());
+        for name in names {
+            let template = self
+                .0
+                .get(name.as_str())
+                .ok_or(CaptiError::template_error(format!(
+                    "Template '{}' is not defined.",
+                    &name
+                )))?;
+
+            deep_merge(&mut result, template.clone());
+        }
+
+        deep_merge(&mut result, test.clone());
+        *test = result;
+
+        Ok(())
+    }
+}
+
+/// Recursively merges `overlay` into `base`. Mappings are merged key by key, and any other value
+/// in `overlay` replaces the value in `base`.
+pub fn deep_merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merges_nested_mappings() {
+        let mut base = serde_yaml::from_str::<Value>(
+            r#"
+            request:
+              headers:
+                Content-Type: application/json
+            "#,
+        )
+        .unwrap();
+        let overlay = serde_yaml::from_str::<Value>(
+            r#"
+            request:
+              method: GET
+              headers:
+                Authorization: Bearer abc
+            "#,
+        )
+        .unwrap();
+
+        deep_merge(&mut base, overlay);
+
+        assert_eq!(base["request"]["method"], Value::from("GET"));
+        assert_eq!(
+            base["request"]["headers"]["Content-Type"],
+            Value::from("application/json")
+        );
+        assert_eq!(
+            base["request"]["headers"]["Authorization"],
+            Value::from("Bearer abc")
+        );
+    }
+
+    #[test]
+    fn applies_templates_in_order() {
+        let templates = serde_yaml::from_str::<Templates>(
+            r#"
+            json_request:
+              request:
+                headers:
+                  Content-Type: application/json
+            error_response:
+              expect:
+                status: 4xx
+                body:
+                  error: $exists
+            "#,
+        )
+        .unwrap();
+
+        let mut test = serde_yaml::from_str::<Value>(
+            r#"
+            test: Invalid request
+            extends: [json_request, error_response]
+            request:
+              method: POST
+              url: http://localhost:3000
+            expect:
+              status: 400
+            "#,
+        )
+        .unwrap();
+
+        templates.apply(&mut test).unwrap();
+
+        assert!(test.get("extends").is_none());
+        assert_eq!(
+            test["request"]["headers"]["Content-Type"],
+            Value::from("application/json")
+        );
+        assert_eq!(test["expect"]["status"], Value::from(400));
+        assert_eq!(test["expect"]["body"]["error"], Value::from("$exists"));
+    }
+
+    #[test]
+    fn errors_on_missing_template() {
+        let templates = Templates::default();
+        let mut test = serde_yaml::from_str::<Value>("extends: missing").unwrap();
+
+        assert!(templates.apply(&mut test).is_err());
+    }
+}
@@ -11,7 +11,7 @@ use serde::{Deserialize, Deserializer, Serialize};
 use crate::{
     errors::CaptiError,
     formatting::indent::Indent,
-    variables::{variable_map::VariableMap, SuiteVariables},
+    variables::{variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences},
 };
 
 use super::{m_match::MMatch, m_value::MValue, match_context::MatchContext};
@@ -47,6 +47,21 @@ impl SuiteVariables for MMap {
     }
 }
 
+impl VariableReferences for MMap {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.map
+            .iter()
+            .flat_map(|(key, value)| {
+                let key = match key {
+                    MValue::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                value.variable_references(&format!("{}.{}", field, key))
+            })
+            .collect()
+    }
+}
+
 impl fmt::Display for MMap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, " ")?;
@@ -7,7 +7,11 @@ use serde::{
 };
 use serde_yaml::Number;
 
-use crate::{errors::CaptiError, formatting::indent::Indent, variables::SuiteVariables};
+use crate::{
+    errors::CaptiError,
+    formatting::indent::Indent,
+    variables::{SuiteVariables, VariableReference, VariableReferences},
+};
 
 use super::{
     m_map::MMap, m_match::MMatch, m_sequence::MSequence, match_context::MatchContext,
@@ -252,6 +256,22 @@ impl SuiteVariables for MValue {
     }
 }
 
+impl VariableReferences for MValue {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        match self {
+            MValue::String(s) => s.variable_references(field),
+            MValue::Sequence(seq) => seq
+                .iter()
+                .enumerate()
+                .flat_map(|(i, value)| value.variable_references(&format!("{}[{}]", field, i)))
+                .collect(),
+            MValue::Mapping(mapping) => mapping.variable_references(field),
+            MValue::Matcher(m) => m.args.variable_references(field),
+            _ => Vec::new(),
+        }
+    }
+}
+
 impl fmt::Display for MValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1,7 +1,12 @@
 use capti::errors::CaptiError;
 use capti::loader::suite_loader::SuiteLoader;
 use capti::reporter::results_reporter::ResultsReporter;
+use capti::runner::run_config::ConfigOptions;
 use capti::runner::runner::Runner;
+use capti::runner::variable_check::VariableCheck;
+use capti::runner::variable_report::print_variables;
+use capti::runner::watch::Watch;
+use capti::suite::setup::processes::{interrupted, terminate_on_interrupt};
 use capti::Args;
 use clap::Parser;
 
@@ -13,10 +18,37 @@ async fn main() -> Result<(), CaptiError> {
     let config = args.config;
 
     let loader = SuiteLoader::new(&path);
-    loader.load_config(&config);
+    loader.load_config(
+        &config,
+        ConfigOptions {
+            environment: args.env,
+            var_files: args.var_files,
+            vars: args.vars,
+        },
+    );
+
+    if args.print_vars {
+        print_variables(&loader.load_suites());
+        return Ok(());
+    }
+
+    if args.watch {
+        terminate_on_interrupt();
+        Watch::new(path, args.jobs, args.strict).run().await;
+        return Ok(());
+    }
 
     let suites = loader.load_suites();
-    let mut runner = Runner::new(suites);
+
+    let variable_check = VariableCheck::new(&suites);
+    variable_check.print_report(args.strict);
+    if args.strict && variable_check.has_errors() {
+        std::process::exit(1);
+    }
+
+    terminate_on_interrupt();
+
+    let mut runner = Runner::new(suites, args.jobs);
 
     let results = runner.run().await;
 
@@ -24,5 +56,9 @@ async fn main() -> Result<(), CaptiError> {
     reporter.print_results();
     reporter.print_summary();
 
+    if interrupted() {
+        std::process::exit(130);
+    }
+
     Ok(())
 }
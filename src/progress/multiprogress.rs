@@ -19,6 +19,9 @@ macro_rules! progress_println {
 
     ($($arg:tt)*) => {
         {
+            // Secrets are masked in everything printed through the progress output. The line is
+            // built before locking, so its arguments are never evaluated while the lock is held.
+            let output = $crate::variables::secrets::redact(&format!($($arg)*));
             let multiprogress = crate::progress::multiprogress::multiprogress()
                 .lock();
 
@@ -32,7 +35,7 @@ macro_rules! progress_println {
 
             if let Ok(multiprogress) = multiprogress {
                 multiprogress.suspend(|| {
-                    println!($($arg)*);
+                    println!("{}", output);
                 });
             }
         }
@@ -6,6 +6,7 @@ use indicatif::{ProgressBar, ProgressStyle};
 use crate::{
     progress_println,
     suite::{report::ReportedResult, test_result::TestResult},
+    variables::secrets,
 };
 
 use super::multiprogress::multiprogress;
@@ -62,7 +63,7 @@ impl Spinner {
                 .expect("Failed to lock multiprogress.");
             multiprogress.remove(&self.spinner);
             multiprogress
-                .println(format!("{}", finish_template))
+                .println(secrets::redact(&finish_template))
                 .expect("Unable to print line after multiprogress.");
         }
 
@@ -92,7 +93,7 @@ impl Spinner {
             .expect("Failed to lock multiprogress.");
         multiprogress.remove(&self.spinner);
         multiprogress
-            .println(format!("{}", finish_template))
+            .println(secrets::redact(&finish_template))
             .expect("Unable to print line after multiprogress.");
     }
 }
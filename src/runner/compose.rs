@@ -0,0 +1,262 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::{
+    errors::CaptiError,
+    formatting::indent::Indent,
+    progress::Spinner,
+    progress_println,
+    suite::setup::{processes, wait_instruction::deserialize_timeout},
+    variables::variable_map::VariableMap,
+};
+
+/// How long to wait for services to start and become healthy, when no `within` timeout is given.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The name the cleanup that takes the services down is registered under.
+const INTERRUPT_CLEANUP: &str = "docker compose down";
+
+/// Lines of each service's logs printed when the run fails.
+const LOG_LINES: &str = "100";
+
+/// A Docker Compose project that is started before any suite runs, and taken down after the run.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ComposeConfig {
+    /// Compose files, relative to the config file. Compose looks for its default file names in
+    /// the config file's directory if none are given.
+    #[serde(default)]
+    files: Vec<PathBuf>,
+    project: Option<String>,
+    /// The services to start. Every service is started if none are given.
+    #[serde(default)]
+    services: Vec<String>,
+    #[serde(default)]
+    build: bool,
+    #[serde(
+        rename = "within",
+        default = "default_timeout",
+        deserialize_with = "deserialize_timeout"
+    )]
+    timeout: Duration,
+    /// Variables to set to the host port published for a service's port, written as
+    /// `service:port`.
+    #[serde(default)]
+    ports: BTreeMap<String, String>,
+    /// Leaves the services running after the run.
+    #[serde(default)]
+    keep_running: bool,
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+fn default_timeout() -> Duration {
+    DEFAULT_TIMEOUT
+}
+
+impl ComposeConfig {
+    /// Resolves the compose files relative to the directory of the config file.
+    pub fn resolve_paths(&mut self, base_dir: &Path) {
+        self.base_dir = base_dir.to_path_buf();
+        self.files = self.files.iter().map(|file| base_dir.join(file)).collect();
+    }
+
+    /// Returns the names of the variables set to published ports.
+    pub fn port_variables(&self) -> impl Iterator<Item = &String> {
+        self.ports.keys()
+    }
+
+    /// Starts the services and waits until they are running, and healthy if they have health
+    /// checks. The published ports are then added to the variables. Services are taken down if
+    /// the run is interrupted.
+    pub async fn up(&self, variables: &mut VariableMap) -> Result<(), CaptiError> {
+        if !self.keep_running {
+            let down = self.command(&["down"]);
+            processes::on_interrupt(INTERRUPT_CLEANUP, move || {
+                let mut down = down;
+                let _ = down.output();
+            });
+        }
+
+        let spinner = Spinner::start("Starting Docker Compose services").await;
+
+        let timeout = self.timeout.as_secs().max(1).to_string();
+        let mut args = vec!["up", "--detach", "--wait", "--wait-timeout", &timeout];
+        if self.build {
+            args.push("--build");
+        }
+        args.extend(self.services.iter().map(String::as_str));
+
+        if let Err(e) = self.run(&args).await {
+            spinner.finish("Failed.");
+            return Err(CaptiError::setup_error(format!(
+                "{}\nlogs:\n{}",
+                e,
+                self.logs().await.indent()
+            )));
+        }
+
+        for (name, target) in self.ports.iter() {
+            let port = self.published_port(target).await.map_err(|e| {
+                CaptiError::setup_error(format!(
+                    "Unable to find the port for variable {}: {}",
+                    name, e
+                ))
+            });
+
+            match port {
+                Ok(port) => variables.insert(name.clone(), port),
+                Err(e) => {
+                    spinner.finish("Failed.");
+                    return Err(e);
+                }
+            }
+        }
+
+        spinner.finish("Done.");
+        progress_println!(" ");
+
+        Ok(())
+    }
+
+    /// Takes the services down, unless they are kept running. When the run failed, their recent
+    /// logs are printed first.
+    pub async fn down(&self, failed: bool) {
+        if failed {
+            let logs = self.logs().await;
+            progress_println!("Docker Compose logs:\n{}", logs.indent());
+        }
+
+        if self.keep_running {
+            return;
+        }
+
+        let spinner = Spinner::start("Stopping Docker Compose services").await;
+        match self.run(&["down"]).await {
+            Ok(_) => spinner.finish("Done."),
+            Err(e) => {
+                spinner.finish("Failed.");
+                progress_println!("{}: {}", "WARN".yellow(), e);
+            }
+        }
+
+        processes::forget_on_interrupt(INTERRUPT_CLEANUP);
+    }
+
+    /// Returns the most recent logs of the services.
+    async fn logs(&self) -> String {
+        let mut args = vec!["logs", "--no-color", "--tail", LOG_LINES];
+        args.extend(self.services.iter().map(String::as_str));
+
+        match self.run(&args).await {
+            Ok(logs) if logs.trim().is_empty() => String::from("(no logs)"),
+            Ok(logs) => logs.trim_end().to_string(),
+            Err(e) => e,
+        }
+    }
+
+    /// Looks up the host port published for a `service:port` target.
+    async fn published_port(&self, target: &str) -> Result<String, String> {
+        let (service, port) = target
+            .rsplit_once(':')
+            .ok_or(format!("Expected 'service:port', found '{}'.", target))?;
+
+        let output = self.run(&["port", service, port]).await?;
+
+        // Prints an address like 0.0.0.0:49153 for each published address
+        output
+            .lines()
+            .filter_map(|address| address.trim().rsplit_once(':'))
+            .map(|(_, port)| port.to_string())
+            .find(|port| port.parse::<u16>().is_ok_and(|port| port != 0))
+            .ok_or(format!(
+                "Port {} of service '{}' is not published.",
+                port, service
+            ))
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new("docker");
+        cmd.arg("compose").current_dir(&self.base_dir);
+
+        if let Some(project) = &self.project {
+            cmd.args(["--project-name", project]);
+        }
+
+        for file in self.files.iter() {
+            cmd.arg("--file").arg(file);
+        }
+
+        cmd.args(args);
+        cmd
+    }
+
+    /// Runs a `docker compose` command, returning its stdout, or a description of the failure
+    /// with its stderr.
+    async fn run(&self, args: &[&str]) -> Result<String, String> {
+        let description = format!("docker compose {}", args.join(" "));
+        let mut cmd = self.command(args);
+
+        let output = tokio::task::spawn_blocking(move || cmd.output())
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Unable to run '{}': {}", description, e))?;
+
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            false => Err(format!(
+                "'{}' failed.\n{}",
+                description,
+                String::from_utf8_lossy(&output.stderr).trim_end().indent()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserializes_compose_config() {
+        let mut config = serde_yaml::from_str::<ComposeConfig>(
+            r#"
+            files:
+              - ../docker-compose.yaml
+            services: [db, app]
+            within: 3m
+            ports:
+              DB_PORT: db:5432
+            "#,
+        )
+        .unwrap();
+        config.resolve_paths(Path::new("tests"));
+
+        assert_eq!(
+            config.files,
+            vec![PathBuf::from("tests/../docker-compose.yaml")]
+        );
+        assert_eq!(config.timeout, Duration::from_secs(180));
+        assert!(!config.build && !config.keep_running);
+        assert_eq!(
+            config.port_variables().collect::<Vec<&String>>(),
+            vec!["DB_PORT"]
+        );
+
+        let args = config
+            .command(&["up"])
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            args,
+            vec!["compose", "--file", "tests/../docker-compose.yaml", "up"]
+        );
+    }
+}
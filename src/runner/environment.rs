@@ -1,10 +1,16 @@
-use std::{fs, ops::Deref, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::Lines,
+};
 
 use colored::Colorize;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use crate::{errors::CaptiError, progress_println};
+use crate::{errors::CaptiError, progress_println, variables::secrets};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(transparent)]
@@ -12,63 +18,218 @@ pub struct Environment {
     path: Option<PathBuf>,
     #[serde(skip)]
     env: IndexMap<String, String>,
+    /// The file each value was last loaded from.
+    #[serde(skip)]
+    sources: HashMap<String, PathBuf>,
 }
 
 impl Environment {
     pub fn load(&mut self) -> Result<(), CaptiError> {
-        match &self.path {
-            Some(path) => {
-                let env_contents = fs::read_to_string(path)
-                    .map_err(|e| CaptiError::FilePathError { source: e })?;
+        match self.path.clone() {
+            Some(path) => self.load_file(&path),
+            None => Ok(()),
+        }
+    }
 
-                progress_println!("Loading environment variables from {:?}", &path);
+    /// Loads an additional env file. Its values override any that are already loaded, and can
+    /// reference them with `${NAME}`.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), CaptiError> {
+        let env_contents =
+            fs::read_to_string(path).map_err(|e| CaptiError::FilePathError { source: e })?;
 
-                self.parse_contents(env_contents);
+        progress_println!("Loading environment variables from {:?}", &path);
 
-                Ok(())
-            }
-            None => Ok(()),
+        for key in self.parse_contents(env_contents) {
+            self.sources.insert(key, path.to_path_buf());
+        }
+
+        // Env files often hold credentials, even in values like connection strings, so every
+        // value is masked
+        for value in self.env.values() {
+            secrets::register(value);
         }
+
+        Ok(())
     }
 
-    fn parse_contents(&mut self, contents: String) {
-        self.env = contents
-            .split('\n')
-            .filter_map(|kv_pair| {
-                if kv_pair.is_empty() {
-                    return None;
-                }
+    /// Returns the path of the env file named in the config file.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the file a value was loaded from.
+    pub fn source(&self, key: &str) -> Option<&Path> {
+        self.sources.get(key).map(PathBuf::as_path)
+    }
 
-                let kv = kv_pair.split("=").collect::<Vec<&str>>();
-                if kv.len() != 2 {
+    /// Parses the contents of an env file into the loaded values, returning the keys it set.
+    fn parse_contents(&mut self, contents: String) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut lines = contents.lines();
+
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line = line
+                .strip_prefix("export ")
+                .map(str::trim_start)
+                .unwrap_or(line);
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if is_valid_key(key.trim()) => (key.trim(), value.trim_start()),
+                _ => {
                     progress_println!(
                         "{}: Invalid key/value pair in env file:\n  {}",
                         "ERROR".red(),
-                        kv_pair
+                        line
                     );
-                    return None;
+                    continue;
                 }
+            };
 
-                let key = kv[0].trim().to_string();
-                let value = kv[1].trim().to_string();
+            let value = match value.chars().next() {
+                Some('"') => self.double_quoted(&value[1..], &mut lines),
+                Some('\'') => single_quoted(&value[1..], &mut lines),
+                _ => Some(self.interpolate(strip_comment(value).trim_end())),
+            };
 
-                let value = match value {
-                    v if v.starts_with("\"") && v.ends_with("\"") => v[1..v.len() - 1].to_string(),
-                    v if v.starts_with("'") && v.ends_with("'") => v[1..v.len() - 1].to_string(),
-                    _ => value,
-                };
+            match value {
+                Some(value) => {
+                    self.env.insert(key.to_string(), value);
+                    keys.push(key.to_string());
+                }
+                None => progress_println!(
+                    "{}: Unterminated quoted value for '{}' in env file.",
+                    "ERROR".red(),
+                    key
+                ),
+            }
+        }
+
+        keys
+    }
+
+    /// Reads a double quoted value up to its closing quote, which may be on a later line.
+    /// Escape sequences and `${NAME}` references are expanded.
+    fn double_quoted(&self, first: &str, lines: &mut Lines) -> Option<String> {
+        let mut value = String::new();
+        let mut line = first;
+
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => return Some(value),
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('r') => value.push('\r'),
+                        Some(escaped @ ('"' | '\\' | '$')) => value.push(escaped),
+                        Some(other) => {
+                            value.push('\\');
+                            value.push(other);
+                        }
+                        None => value.push('\\'),
+                    },
+                    '$' if chars.peek() == Some(&'{') => {
+                        chars.next();
+                        let reference =
+                            chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                        value.push_str(&self.lookup(&reference));
+                    }
+                    c => value.push(c),
+                }
+            }
+
+            value.push('\n');
+            line = lines.next()?;
+        }
+    }
+
+    /// Expands `${NAME}` references in an unquoted value.
+    fn interpolate(&self, text: &str) -> String {
+        let mut value = String::new();
+        let mut chars = text.chars().peekable();
 
-                Some((key, value))
-            })
-            .collect::<IndexMap<String, String>>();
+        while let Some(c) = chars.next() {
+            match c {
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    let reference = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                    value.push_str(&self.lookup(&reference));
+                }
+                c => value.push(c),
+            }
+        }
+
+        value
+    }
+
+    /// Resolves a `NAME` or `NAME:-default` reference from the values loaded so far, then the
+    /// process environment. Unknown names resolve to an empty string.
+    fn lookup(&self, reference: &str) -> String {
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name.trim(), Some(default)),
+            None => (reference.trim(), None),
+        };
+
+        let value = self
+            .env
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok());
+
+        match (value, default) {
+            (Some(value), Some(default)) if value.is_empty() => default.to_string(),
+            (Some(value), _) => value,
+            (None, Some(default)) => default.to_string(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Reads a single quoted value up to its closing quote, which may be on a later line. The
+/// contents are taken literally.
+fn single_quoted(first: &str, lines: &mut Lines) -> Option<String> {
+    let mut value = String::new();
+    let mut line = first;
+
+    loop {
+        match line.split_once('\'') {
+            Some((end, _)) => {
+                value.push_str(end);
+                return Some(value);
+            }
+            None => {
+                value.push_str(line);
+                value.push('\n');
+                line = lines.next()?;
+            }
+        }
     }
 }
 
+/// Removes a trailing ` # comment` from an unquoted value.
+fn strip_comment(value: &str) -> &str {
+    match value.find(" #").or(value.find("\t#")) {
+        Some(index) => &value[..index],
+        None => value,
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(char::is_whitespace)
+}
+
 impl Default for Environment {
     fn default() -> Environment {
         Environment {
             path: None,
             env: IndexMap::new(),
+            sources: HashMap::new(),
         }
     }
 }
@@ -84,6 +245,21 @@ impl Deref for Environment {
 mod test {
     use super::*;
 
+    #[test]
+    fn masks_every_loaded_value() {
+        let path = std::env::temp_dir().join(format!("capti-env-{}", std::process::id()));
+        fs::write(&path, "DB_URL=postgres://admin:hunter22@db/app\n").unwrap();
+
+        let mut env = Environment::default();
+        env.load_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            secrets::redact("connecting to postgres://admin:hunter22@db/app"),
+            format!("connecting to {}", secrets::MASK)
+        );
+    }
+
     #[test]
     fn properly_parses_env_file_string() {
         let mut env = Environment::default();
@@ -103,4 +279,51 @@ mod test {
         assert_eq!(env.get("ABC"), Some(&"123".to_string()));
         assert_eq!(env.get("XYZ"), Some(&"456".to_string()));
     }
+
+    #[test]
+    fn parses_comments_exports_and_equals_in_values() {
+        let mut env = Environment::default();
+        let contents =
+            "# database\nexport DB_URL=postgres://host/db?sslmode=require # prod\n\nTOKEN=abc=="
+                .to_string();
+        env.parse_contents(contents);
+        assert_eq!(
+            env.get("DB_URL"),
+            Some(&"postgres://host/db?sslmode=require".to_string())
+        );
+        assert_eq!(env.get("TOKEN"), Some(&"abc==".to_string()));
+        assert_eq!(env.len(), 2);
+    }
+
+    #[test]
+    fn parses_multiline_and_escaped_values() {
+        let mut env = Environment::default();
+        let contents =
+            "KEY=\"-----BEGIN-----\nabc\n-----END-----\"\nESCAPED=\"a\\tb \\\"c\\\" \\${HOME}\"\nLITERAL='${NOPE}\\n'"
+                .to_string();
+        env.parse_contents(contents);
+        assert_eq!(
+            env.get("KEY"),
+            Some(&"-----BEGIN-----\nabc\n-----END-----".to_string())
+        );
+        assert_eq!(env.get("ESCAPED"), Some(&"a\tb \"c\" ${HOME}".to_string()));
+        assert_eq!(env.get("LITERAL"), Some(&"${NOPE}\\n".to_string()));
+    }
+
+    #[test]
+    fn interpolates_earlier_values() {
+        let mut env = Environment::default();
+        let contents =
+            "HOST=localhost\nURL=http://${HOST}:${PORT:-3000}\nQUOTED=\"${URL}/api\"".to_string();
+        env.parse_contents(contents);
+        assert_eq!(env.get("URL"), Some(&"http://localhost:3000".to_string()));
+        assert_eq!(
+            env.get("QUOTED"),
+            Some(&"http://localhost:3000/api".to_string())
+        );
+
+        env.parse_contents("HOST=staging.example.com\nPORT=443".to_string());
+        assert_eq!(env.get("HOST"), Some(&"staging.example.com".to_string()));
+        assert_eq!(env.get("URL"), Some(&"http://localhost:3000".to_string()));
+    }
 }
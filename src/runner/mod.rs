@@ -1,3 +1,9 @@
+pub mod compose;
 mod environment;
+pub mod profile;
 pub mod run_config;
 pub mod runner;
+mod schedule;
+pub mod variable_check;
+pub mod variable_report;
+pub mod watch;
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::{
+    client::ClientConfig,
+    variables::variable_map::{deserialize_definitions, VariableMap},
+};
+
+/// A named environment in the config file, such as `local` or `staging`, selected with the
+/// `--env` argument. Its settings are layered over the rest of the config.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct EnvironmentProfile {
+    /// Env files loaded after the config's `env_file`, with later files taking precedence.
+    #[serde(default)]
+    pub env_files: Vec<PathBuf>,
+    /// Variables that override the variables defined by each suite.
+    #[serde(default, deserialize_with = "deserialize_definitions")]
+    pub variables: VariableMap,
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub client: ClientConfig,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserializes_environment_profiles() {
+        let profile = serde_yaml::from_str::<EnvironmentProfile>(
+            r#"
+            env_files:
+              - .env
+              - .env.staging
+            base_url: https://staging.example.com
+            variables:
+              USER_ID: 42
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            profile.env_files,
+            vec![PathBuf::from(".env"), PathBuf::from(".env.staging")]
+        );
+        assert_eq!(
+            profile.base_url,
+            Some(String::from("https://staging.example.com"))
+        );
+        assert!(profile.variables.get("USER_ID").is_some());
+        assert_eq!(profile.client, ClientConfig::default());
+    }
+}
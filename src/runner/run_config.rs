@@ -1,22 +1,67 @@
-use std::{path::PathBuf, sync::Mutex};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
 
 use colored::Colorize;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
-use crate::{progress_println, suite::setup::SuiteSetup};
+use crate::{
+    client::{oauth2::OAuth2Provider, ClientConfig},
+    errors::CaptiError,
+    loader::templates::Templates,
+    m_value::m_value::MValue,
+    progress_println,
+    suite::setup::SuiteSetup,
+    variables::{source::VariableSource, variable_map::VariableMap},
+};
 
-use super::environment::Environment;
+use super::{compose::ComposeConfig, environment::Environment, profile::EnvironmentProfile};
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct RunConfig {
     pub setup: Option<SuiteSetup>,
+    pub compose: Option<ComposeConfig>,
     #[serde(default, rename = "env_file")]
     pub env: Environment,
+    #[serde(default)]
+    pub templates: Templates,
+    #[serde(default)]
+    pub template_files: Vec<PathBuf>,
+    pub max_concurrency: Option<usize>,
+    #[serde(default)]
+    pub client: ClientConfig,
+    #[serde(default)]
+    pub oauth2: HashMap<String, OAuth2Provider>,
+    #[serde(default)]
+    pub environments: HashMap<String, EnvironmentProfile>,
+    /// The name of the environment selected with `--env`.
+    #[serde(skip)]
+    pub environment: Option<String>,
+    /// Variables from the selected environment, which override suite variables.
+    #[serde(skip)]
+    pub variables: VariableMap,
+    /// Prepended to request urls that start with `/`.
+    #[serde(skip)]
+    pub base_url: Option<String>,
+    /// Where each of the overriding `variables` came from.
+    #[serde(skip)]
+    pub variable_sources: HashMap<String, VariableSource>,
+}
+
+/// Settings from the command line that are layered over the config file.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOptions {
+    pub environment: Option<String>,
+    pub var_files: Vec<PathBuf>,
+    pub vars: Vec<(String, String)>,
 }
 
 impl RunConfig {
-    pub fn load(config_path: Option<PathBuf>) {
+    pub fn load(config_path: Option<PathBuf>, options: ConfigOptions) {
         match &config_path {
             Some(path) => {
                 progress_println!("Loading configuration from {:?}", path)
@@ -31,11 +76,139 @@ impl RunConfig {
 
         drop(path);
 
+        let mut config_options = CONFIG_OPTIONS
+            .lock()
+            .expect("Failed to load configuration file:\n  Static lock unavailable.");
+        *config_options = options;
+
+        drop(config_options);
+
         Lazy::force(&CONFIG);
     }
 
-    pub fn global() -> &'static RunConfig {
-        &CONFIG
+    pub fn global() -> Arc<RunConfig> {
+        CONFIG
+            .read()
+            .expect("Failed to read configuration:\n  Static lock unavailable.")
+            .clone()
+    }
+
+    /// Loads the configuration again from the same file and command line options, so changes
+    /// to the config or env files are picked up in watch mode. If it can't be loaded, the
+    /// previous configuration is kept.
+    pub fn reload() -> Result<(), CaptiError> {
+        let config = {
+            let config_path = CONFIG_PATH.lock().unwrap();
+            let options = CONFIG_OPTIONS.lock().unwrap();
+            load_config(config_path.as_ref(), &options)?
+        };
+
+        let mut global = CONFIG
+            .write()
+            .expect("Failed to reload configuration:\n  Static lock unavailable.");
+        *global = Arc::new(config);
+
+        Ok(())
+    }
+
+    /// Returns the files the configuration was loaded from: the config file itself, along with
+    /// any env, variable, and template files it uses.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let config_path = CONFIG_PATH.lock().unwrap().clone();
+        let base_dir = config_path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+
+        let mut files = config_path.into_iter().collect::<Vec<PathBuf>>();
+        files.extend(self.env.path().map(Path::to_path_buf));
+
+        if let Some(profile) = self
+            .environment
+            .as_ref()
+            .and_then(|name| self.environments.get(name))
+        {
+            files.extend(profile.env_files.iter().map(|file| base_dir.join(file)));
+        }
+
+        files.extend(CONFIG_OPTIONS.lock().unwrap().var_files.iter().cloned());
+        files.extend(self.template_files.iter().map(|file| base_dir.join(file)));
+
+        files
+    }
+
+    /// Layers the settings of the named environment over the rest of the config. Its env files
+    /// are relative to `base_dir`, the directory of the config file.
+    fn apply_environment(&mut self, name: &str, base_dir: &Path) -> Result<(), CaptiError> {
+        let profile = match self.environments.get(name) {
+            Some(profile) => profile.clone(),
+            None => {
+                let mut available = self.environments.keys().cloned().collect::<Vec<String>>();
+                available.sort();
+                return Err(CaptiError::environment_error(format!(
+                    "Environment '{}' is not defined in the config file. Available environments: {}",
+                    name,
+                    match available.is_empty() {
+                        true => String::from("none"),
+                        false => available.join(", "),
+                    }
+                )));
+            }
+        };
+
+        progress_println!("Using environment '{}'", name);
+
+        for path in profile.env_files.iter() {
+            self.env.load_file(&base_dir.join(path))?;
+        }
+
+        self.client = self.client.merge(&profile.client);
+
+        let mut variables = profile.variables;
+        if let Some(base_url) = profile.base_url {
+            variables.insert_if_absent("BASE_URL", base_url.as_str());
+            self.base_url = Some(base_url);
+        }
+        self.override_variables(variables, VariableSource::Environment(name.to_string()));
+        self.environment = Some(name.to_string());
+
+        Ok(())
+    }
+
+    /// Layers variables from `--var-file` and `--var` arguments over the selected environment.
+    /// Later files take precedence over earlier ones, and `--var` over every file.
+    fn apply_overrides(&mut self, options: &ConfigOptions) -> Result<(), CaptiError> {
+        for path in options.var_files.iter() {
+            progress_println!("Loading variables from {:?}", path);
+            let contents = fs::read_to_string(path)?;
+            let variables = serde_yaml::from_str::<VariableMap>(&contents)?;
+            self.override_variables(variables, VariableSource::VarFile(path.clone()));
+        }
+
+        let mut variables = VariableMap::new();
+        for (key, value) in options.vars.iter() {
+            variables.insert(key.clone(), value.as_str());
+        }
+        self.override_variables(variables, VariableSource::Argument);
+
+        // A base url passed in directly also applies to relative request urls
+        if let Some(VariableSource::Argument | VariableSource::VarFile(_)) =
+            self.variable_sources.get("BASE_URL")
+        {
+            if let Some(MValue::String(base_url)) = HashMap::get(&self.variables, "BASE_URL") {
+                self.base_url = Some(base_url.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn override_variables(&mut self, variables: VariableMap, source: VariableSource) {
+        for name in variables.keys() {
+            self.variable_sources.insert(name.clone(), source.clone());
+        }
+        self.variables.extend(&variables);
     }
 }
 
@@ -43,21 +216,48 @@ impl Default for RunConfig {
     fn default() -> RunConfig {
         RunConfig {
             setup: None,
+            compose: None,
             env: Environment::default(),
+            templates: Templates::default(),
+            template_files: Vec::new(),
+            max_concurrency: None,
+            client: ClientConfig::default(),
+            oauth2: HashMap::new(),
+            environments: HashMap::new(),
+            environment: None,
+            variables: VariableMap::new(),
+            base_url: None,
+            variable_sources: HashMap::new(),
         }
     }
 }
 
 static CONFIG_PATH: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
-static CONFIG: Lazy<RunConfig> = Lazy::new(|| {
+static CONFIG_OPTIONS: Lazy<Mutex<ConfigOptions>> = Lazy::new(Default::default);
+static CONFIG: Lazy<RwLock<Arc<RunConfig>>> = Lazy::new(|| {
     let config_path = CONFIG_PATH.lock().unwrap();
-    load_config(config_path.as_ref())
+    let options = CONFIG_OPTIONS.lock().unwrap();
+    match load_config(config_path.as_ref(), &options) {
+        Ok(config) => RwLock::new(Arc::new(config)),
+        Err(e) => {
+            progress_println!("{}: {}", "ERROR".red(), e);
+            std::process::exit(1);
+        }
+    }
 });
 
-fn load_config(config_path: Option<&PathBuf>) -> RunConfig {
+/// Loads the config file and applies the command line options. Errors that would run the
+/// suites against something other than what was intended, like an unknown environment, are
+/// returned.
+fn load_config(
+    config_path: Option<&PathBuf>,
+    options: &ConfigOptions,
+) -> Result<RunConfig, CaptiError> {
     let config = match config_path {
         Some(path) => match std::fs::read_to_string(path) {
-            Ok(config) => serde_yaml::from_str::<RunConfig>(&config).ok(),
+            Ok(config) => Some(serde_yaml::from_str::<RunConfig>(&config).map_err(|e| {
+                CaptiError::config_error(format!("Unable to parse {:?}:\n  {}", path, e))
+            })?),
             Err(e) => {
                 progress_println!("{}: Failed to read config file:\n  {}", "ERROR".red(), e);
                 None
@@ -67,6 +267,15 @@ fn load_config(config_path: Option<&PathBuf>) -> RunConfig {
     };
 
     let mut config = config.unwrap_or_default();
+
+    let base_dir = config_path
+        .and_then(|path| path.parent())
+        .unwrap_or(Path::new("."));
+    config.client.resolve_paths(base_dir);
+    for profile in config.environments.values_mut() {
+        profile.client.resolve_paths(base_dir);
+    }
+
     if let Err(e) = config.env.load() {
         progress_println!(
             "{}: Failed to load env file specified in configuration:\n  {}",
@@ -75,5 +284,25 @@ fn load_config(config_path: Option<&PathBuf>) -> RunConfig {
         );
     }
 
-    config
+    if let Some(environment) = &options.environment {
+        // Running against the wrong environment is never what was intended
+        config.apply_environment(environment, base_dir)?;
+    }
+
+    config.apply_overrides(options)?;
+
+    if let Some(compose) = &mut config.compose {
+        compose.resolve_paths(base_dir);
+    }
+
+    let template_files = config.template_files.clone();
+    if let Err(e) = config.templates.load_files(&template_files, base_dir) {
+        progress_println!(
+            "{}: Failed to load template files specified in configuration:\n  {}",
+            "ERROR".red(),
+            e
+        );
+    }
+
+    Ok(config)
 }
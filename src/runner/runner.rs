@@ -1,34 +1,302 @@
-use crate::{progress_println, suite::report::TestResultsReport, Suite};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
-use super::run_config::RunConfig;
+use colored::Colorize;
+use futures::StreamExt;
+use tokio::sync::Semaphore;
+
+use crate::{
+    client::Client,
+    errors::CaptiError,
+    progress_println,
+    suite::{
+        report::TestResultsReport,
+        setup::{suite_setup::SetupContext, Processes, SuiteSetup},
+    },
+    variables::variable_map::VariableMap,
+    Suite,
+};
+
+use super::{run_config::RunConfig, schedule::Schedule};
 
 pub struct Runner {
     suites: Vec<Suite>,
+    max_concurrency: Option<usize>,
+    /// Shared by every suite, so that no more than `max_concurrency` requests are sent at once
+    /// across the whole run.
+    requests: Option<Semaphore>,
+    /// The background processes of each suite, when they are kept running after the suite
+    /// finishes. Otherwise, they are stopped as soon as each suite finishes.
+    processes: Option<Vec<Arc<Processes>>>,
 }
 
 impl Runner {
-    pub fn new(suites: Vec<Suite>) -> Self {
-        Runner { suites }
+    /// Creates a runner for the provided suites. The maximum concurrency, if provided, takes
+    /// precedence over the `max_concurrency` setting in the config file.
+    pub fn new(suites: Vec<Suite>, max_concurrency: Option<usize>) -> Self {
+        let max_concurrency = max_concurrency
+            .or(RunConfig::global().max_concurrency)
+            .map(|limit| limit.max(1));
+
+        Runner {
+            suites,
+            max_concurrency,
+            requests: max_concurrency.map(Semaphore::new),
+            processes: None,
+        }
+    }
+
+    /// Tracks the background processes started by each suite's setup in the given processes,
+    /// one for each suite, and leaves them running after the suites finish.
+    pub fn keep_processes(mut self, processes: Vec<Arc<Processes>>) -> Self {
+        self.processes = Some(processes);
+        self
     }
 
     pub async fn run(&mut self) -> Vec<TestResultsReport> {
-        if let Some(setup) = &RunConfig::global().setup {
-            progress_println!("Running test setup scripts");
-            setup.execute_before_all().await;
+        let mut setup = RunSetup::start().await;
+
+        let reports = self.run_with(&mut setup, &HashMap::new()).await;
+
+        let failed = reports
+            .iter()
+            .any(|report| report.failed > 0 || report.errors > 0);
+        setup.finish(failed).await;
+
+        reports
+    }
+
+    /// Runs the suites after the run's setup has started. Suites that ran earlier in watch mode
+    /// are listed in `previous` along with whether they failed, and count as completed
+    /// dependencies.
+    pub async fn run_with(
+        &mut self,
+        setup: &mut RunSetup,
+        previous: &HashMap<String, bool>,
+    ) -> Vec<TestResultsReport> {
+        match setup.failed {
+            Some(hook) => self
+                .suites
+                .iter()
+                .map(|suite| suite.setup_failed(hook))
+                .collect(),
+            None => self.run_suites(&mut setup.exports, previous).await,
+        }
+    }
+
+    /// Runs the suites in the order their dependencies require. Suites whose dependencies failed
+    /// are skipped.
+    async fn run_suites(
+        &mut self,
+        exports: &mut VariableMap,
+        previous: &HashMap<String, bool>,
+    ) -> Vec<TestResultsReport> {
+        match self.max_concurrency {
+            Some(limit) => progress_println!(
+                "Running {} test suites with a maximum concurrency of {}.",
+                self.suites.len(),
+                limit
+            ),
+            None => progress_println!(
+                "Running {} test suites with unlimited concurrency.",
+                self.suites.len()
+            ),
+        }
+
+        // Dependencies that already ran aren't waited on
+        let names = self
+            .suites
+            .iter()
+            .map(|suite| suite.suite.as_str())
+            .collect::<HashSet<&str>>();
+        let depends_on = self
+            .suites
+            .iter()
+            .map(|suite| {
+                suite
+                    .depends_on
+                    .iter()
+                    .filter(|d| names.contains(d.as_str()) || !previous.contains_key(*d))
+                    .cloned()
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<Vec<String>>>();
+
+        let schedule = Schedule::new(
+            &self
+                .suites
+                .iter()
+                .zip(depends_on.iter())
+                .map(|(suite, depends_on)| (suite.suite.as_str(), depends_on.as_slice()))
+                .collect::<Vec<(&str, &[String])>>(),
+        );
+
+        let mut reports = self
+            .suites
+            .iter()
+            .map(|_| None)
+            .collect::<Vec<Option<TestResultsReport>>>();
+
+        let keep_processes = self.processes.is_some();
+        let processes = self.processes.clone().unwrap_or_else(|| {
+            self.suites
+                .iter()
+                .map(|_| Arc::default())
+                .collect::<Vec<Arc<Processes>>>()
+        });
+
+        let mut failed = previous
+            .iter()
+            .filter(|(_, failed)| **failed)
+            .map(|(name, _)| name.clone())
+            .collect::<HashSet<String>>();
+        for (i, reason) in schedule.unresolved {
+            failed.insert(self.suites[i].suite.clone());
+            reports[i] = Some(self.suites[i].skip(reason));
+        }
+
+        for level in schedule.levels {
+            let mut futures = Vec::new();
+            let mut indexes = Vec::new();
+
+            for (i, suite) in self.suites.iter_mut().enumerate() {
+                if !level.contains(&i) {
+                    continue;
+                }
+
+                if let Some(dependency) = suite.depends_on.iter().find(|d| failed.contains(*d)) {
+                    reports[i] = Some(suite.skip(format!(
+                        "Dependency '{}' did not complete successfully.",
+                        dependency
+                    )));
+                    failed.insert(suite.suite.clone());
+                    continue;
+                }
+
+                suite.import_variables(exports);
+                let requests = self.requests.as_ref();
+                let processes = &processes[i];
+                futures.push(async move {
+                    let report = suite.run(requests, processes).await;
+                    if !keep_processes {
+                        processes.terminate().await;
+                    }
+                    report
+                });
+                indexes.push(i);
+            }
+
+            let limit = self.max_concurrency.unwrap_or(futures.len()).max(1);
+            let results = futures::stream::iter(futures)
+                .buffered(limit)
+                .collect::<Vec<TestResultsReport>>()
+                .await;
+
+            for (i, report) in indexes.into_iter().zip(results) {
+                if report.failed > 0 || report.errors > 0 {
+                    failed.insert(self.suites[i].suite.clone());
+                }
+
+                exports.extend(&self.suites[i].exported_variables());
+
+                reports[i] = Some(report);
+            }
         }
 
-        let mut futures = Vec::new();
-        for suite in self.suites.iter_mut() {
-            let future = suite.run();
-            futures.push(future);
+        reports.into_iter().flatten().collect()
+    }
+}
+
+/// The Docker Compose services and config setup hooks that run around the suites, along with the
+/// variables they publish. In watch mode, these are kept running between runs.
+pub struct RunSetup {
+    config: Arc<RunConfig>,
+    processes: Processes,
+    /// Setup hooks send requests with the client settings from the config file
+    hooks: Option<(SuiteSetup, Client)>,
+    /// Variables published by Docker Compose, extracted by the config's setup hooks, and
+    /// exported by completed suites, available to every suite that runs afterward.
+    exports: VariableMap,
+    /// The hook or step that failed, if the suites can't be run
+    failed: Option<&'static str>,
+}
+
+impl RunSetup {
+    /// Starts the Docker Compose services and runs the config's `before_all` hook.
+    pub async fn start() -> Self {
+        let config = RunConfig::global();
+        let mut setup = RunSetup {
+            config: config.clone(),
+            processes: Processes::default(),
+            hooks: None,
+            exports: VariableMap::new(),
+            failed: None,
+        };
+
+        if let Some(compose) = &config.compose {
+            if let Err(e) = compose.up(&mut setup.exports).await {
+                progress_println!("{}: {}", "ERROR".red(), e);
+                setup.failed = Some("compose");
+            }
         }
 
-        let results = futures::future::join_all(futures).await;
+        if let Some(hooks) = &config.setup {
+            match Client::build(&config.client) {
+                Ok(client) => setup.hooks = Some((hooks.clone(), client)),
+                Err(e) => {
+                    progress_println!(
+                        "{}: {}",
+                        "ERROR".red(),
+                        CaptiError::setup_error(e.to_string())
+                    );
+                    setup.failed = setup.failed.or(Some("before_all"));
+                }
+            }
+        }
 
-        if let Some(setup) = &RunConfig::global().setup {
-            setup.execute_after_all().await;
+        if let (Some((hooks, client)), None) = (&setup.hooks, setup.failed) {
+            progress_println!("Running test setup scripts");
+            let context = SetupContext::new(&setup.processes, client, &mut setup.exports);
+            if let Err(e) = hooks.execute_before_all(context).await {
+                progress_println!("{}: {}", "ERROR".red(), e);
+                setup.failed = Some("before_all");
+            }
         }
 
-        results
+        setup
+    }
+
+    /// Runs the config's teardown hooks, stops the setup processes, and takes down the Docker
+    /// Compose services.
+    pub async fn finish(mut self, failed: bool) {
+        if let Some((hooks, client)) = &self.hooks {
+            if failed {
+                let context = SetupContext::new(&self.processes, client, &mut self.exports);
+                if let Err(e) = hooks.execute_on_failure(context).await {
+                    progress_println!("{}: {}", "ERROR".red(), e);
+                }
+            }
+
+            if self.failed.is_none() {
+                let context = SetupContext::new(&self.processes, client, &mut self.exports);
+                if let Err(e) = hooks.execute_after_all(context).await {
+                    progress_println!("{}: {}", "ERROR".red(), e);
+                }
+            }
+
+            let context = SetupContext::new(&self.processes, client, &mut self.exports);
+            if let Err(e) = hooks.execute_always(context).await {
+                progress_println!("{}: {}", "ERROR".red(), e);
+            }
+        }
+
+        self.processes.terminate().await;
+
+        if let Some(compose) = &self.config.compose {
+            // The logs are already included in the error if the services didn't start
+            compose.down(failed && self.failed != Some("compose")).await;
+        }
     }
 }
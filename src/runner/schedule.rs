@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+/// The order in which suites should run based on their dependencies. Each level contains the
+/// indexes of suites whose dependencies have all run in earlier levels, so the suites in a level
+/// can run concurrently. Suites that can never be scheduled are listed with the reason.
+#[derive(Debug, Default, PartialEq)]
+pub struct Schedule {
+    pub levels: Vec<Vec<usize>>,
+    pub unresolved: Vec<(usize, String)>,
+}
+
+impl Schedule {
+    /// Builds the schedule from each suite's name and the names of the suites it depends on.
+    pub fn new(suites: &[(&str, &[String])]) -> Self {
+        let mut schedule = Schedule::default();
+        let names = suites
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<HashSet<&str>>();
+
+        let mut pending = Vec::new();
+        for (i, (_, depends_on)) in suites.iter().enumerate() {
+            match depends_on.iter().find(|dep| !names.contains(dep.as_str())) {
+                Some(missing) => schedule.unresolved.push((
+                    i,
+                    format!("Dependency '{}' is not a loaded suite.", missing),
+                )),
+                None => pending.push(i),
+            }
+        }
+
+        // Suites with an unresolved dependency can never run, so neither can their dependents.
+        loop {
+            let blocked = schedule
+                .unresolved
+                .iter()
+                .map(|(i, _)| suites[*i].0)
+                .collect::<HashSet<&str>>();
+
+            let (newly_blocked, remaining): (Vec<usize>, Vec<usize>) =
+                pending.into_iter().partition(|i| {
+                    suites[*i]
+                        .1
+                        .iter()
+                        .any(|dep| blocked.contains(dep.as_str()))
+                });
+            pending = remaining;
+
+            if newly_blocked.is_empty() {
+                break;
+            }
+
+            for i in newly_blocked {
+                schedule
+                    .unresolved
+                    .push((i, String::from("A dependency of this suite cannot run.")));
+            }
+        }
+
+        let mut completed = HashSet::new();
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<usize>, Vec<usize>) = pending.into_iter().partition(|i| {
+                suites[*i]
+                    .1
+                    .iter()
+                    .all(|dep| completed.contains(dep.as_str()))
+            });
+
+            if ready.is_empty() {
+                for i in waiting {
+                    schedule.unresolved.push((
+                        i,
+                        String::from(
+                            "Circular dependency detected in the dependencies of this suite.",
+                        ),
+                    ));
+                }
+                break;
+            }
+
+            // A suite name may be shared by multiple suites, so a dependency is only complete
+            // once every suite with that name has run.
+            for i in ready.iter() {
+                let name = suites[*i].0;
+                let all_done = waiting.iter().all(|w| suites[*w].0 != name);
+                if all_done {
+                    completed.insert(name);
+                }
+            }
+
+            schedule.levels.push(ready);
+            pending = waiting;
+        }
+
+        schedule
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn schedules_independent_suites_together() {
+        let none: Vec<String> = vec![];
+        let schedule = Schedule::new(&[("a", &none), ("b", &none)]);
+
+        assert_eq!(schedule.levels, vec![vec![0, 1]]);
+        assert!(schedule.unresolved.is_empty());
+    }
+
+    #[test]
+    fn schedules_dependents_after_dependencies() {
+        let none: Vec<String> = vec![];
+        let login = vec![String::from("login")];
+        let schedule = Schedule::new(&[("recipes", &login), ("login", &none), ("users", &login)]);
+
+        assert_eq!(schedule.levels, vec![vec![1], vec![0, 2]]);
+    }
+
+    #[test]
+    fn reports_missing_and_circular_dependencies() {
+        let a = vec![String::from("a")];
+        let b = vec![String::from("b")];
+        let missing = vec![String::from("missing")];
+        let c = vec![String::from("c")];
+        let schedule = Schedule::new(&[("a", &b), ("b", &a), ("c", &missing), ("d", &c)]);
+
+        assert!(schedule.levels.is_empty());
+        assert_eq!(schedule.unresolved.len(), 4);
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use colored::Colorize;
+
+use crate::{progress_println, variables::VariableReference, Suite};
+
+/// How a variable that isn't defined when the suite is loaded will be resolved, if at all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Extracted by an earlier test in the suite.
+    Extracted(String),
+    /// Only extracted by this test or a later test, so it isn't available when it's used.
+    ExtractedLater(String),
+    Undefined,
+}
+
+/// A variable reference that can't be resolved from the suite variables, exported variables,
+/// the environment, or the env file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnresolvedVariable {
+    pub suite: String,
+    pub test: Option<String>,
+    pub reference: VariableReference,
+    pub resolution: Resolution,
+}
+
+impl UnresolvedVariable {
+    pub fn is_error(&self) -> bool {
+        !matches!(self.resolution, Resolution::Extracted(_))
+    }
+}
+
+/// Checks every suite for variable references that won't resolve, before any tests run.
+pub struct VariableCheck {
+    unresolved: Vec<UnresolvedVariable>,
+}
+
+impl VariableCheck {
+    pub fn new(suites: &[Suite]) -> Self {
+        let suites_by_name = suites
+            .iter()
+            .map(|suite| (suite.suite.as_str(), suite))
+            .collect::<HashMap<&str, &Suite>>();
+
+        let unresolved = suites
+            .iter()
+            .flat_map(|suite| {
+                suite.unresolved_variables(&imported_variables(suite, &suites_by_name))
+            })
+            .collect();
+
+        VariableCheck { unresolved }
+    }
+
+    /// Returns true if any variable is defined nowhere, or is used before it is extracted.
+    pub fn has_errors(&self) -> bool {
+        self.unresolved.iter().any(|variable| variable.is_error())
+    }
+
+    /// Prints the unresolved variables if any of them are errors. In strict mode they are
+    /// reported as errors rather than warnings.
+    pub fn print_report(&self, strict: bool) {
+        if !self.has_errors() {
+            return;
+        }
+
+        let label = match strict {
+            true => "ERROR".red(),
+            false => "WARN".yellow(),
+        };
+
+        let count = self
+            .unresolved
+            .iter()
+            .filter(|variable| variable.is_error())
+            .count();
+
+        progress_println!(
+            "{}: Found {} variable reference{} that will not resolve:\n{}",
+            label,
+            count,
+            if count == 1 { "" } else { "s" },
+            self
+        );
+    }
+}
+
+/// The variables exported by every suite the suite depends on, directly or indirectly.
+pub(crate) fn imported_variables(suite: &Suite, suites: &HashMap<&str, &Suite>) -> HashSet<String> {
+    let mut queue = suite
+        .depends_on
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<&str>>();
+    let mut visited = HashSet::new();
+    let mut imported = HashSet::new();
+
+    while let Some(name) = queue.pop() {
+        if !visited.insert(name) {
+            continue;
+        }
+
+        if let Some(dependency) = suites.get(name) {
+            imported.extend(dependency.export.iter().cloned());
+            queue.extend(dependency.depends_on.iter().map(String::as_str));
+        }
+    }
+
+    imported
+}
+
+impl fmt::Display for VariableCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut location = None;
+
+        for variable in self.unresolved.iter() {
+            let current = (&variable.suite, &variable.test);
+            if location != Some(current) {
+                match &variable.test {
+                    Some(test) => writeln!(f, "  [{}] {}", &variable.suite, test)?,
+                    None => writeln!(f, "  [{}] suite variables", &variable.suite)?,
+                }
+                location = Some(current);
+            }
+
+            let reference = format!("${{{}}}", &variable.reference.name);
+            let resolution = match &variable.resolution {
+                Resolution::Extracted(test) => format!("will be extracted by '{}'", test).dimmed(),
+                Resolution::ExtractedLater(test) => {
+                    format!("is not extracted until '{}'", test).yellow()
+                }
+                Resolution::Undefined => "is not defined".red(),
+            };
+
+            writeln!(
+                f,
+                "    {}: {} {}",
+                &variable.reference.field, reference, resolution
+            )?;
+        }
+
+        Ok(())
+    }
+}
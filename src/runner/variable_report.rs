@@ -0,0 +1,48 @@
+use std::collections::{HashMap, HashSet};
+
+use colored::Colorize;
+
+use crate::{
+    progress_println,
+    variables::source::{ResolvedVariable, VariableSource},
+    Suite,
+};
+
+use super::variable_check::imported_variables;
+
+/// Prints the final value of every variable in every suite, and where each value comes from.
+pub fn print_variables(suites: &[Suite]) {
+    let suites_by_name = suites
+        .iter()
+        .map(|suite| (suite.suite.as_str(), suite))
+        .collect::<HashMap<&str, &Suite>>();
+
+    for suite in suites.iter() {
+        let imported: HashSet<String> = imported_variables(suite, &suites_by_name);
+        let variables = suite.resolved_variables(&imported);
+
+        let lines = match variables.is_empty() {
+            true => String::from("  (none)"),
+            false => variables
+                .iter()
+                .map(format_variable)
+                .collect::<Vec<String>>()
+                .join("\n"),
+        };
+
+        progress_println!("Variables for [{}]:\n{}\n", &suite.suite, lines);
+    }
+}
+
+fn format_variable(variable: &ResolvedVariable) -> String {
+    let source = format!("({})", variable.source);
+    let source = match variable.source {
+        VariableSource::Undefined => source.red(),
+        _ => source.dimmed(),
+    };
+
+    match &variable.value {
+        Some(value) => format!("  {} = {} {}", variable.name, value, source),
+        None => format!("  {} {}", variable.name, source),
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use colored::Colorize;
+use serde_yaml::Value;
+
+use crate::{
+    loader::{includes::included_files, suite_loader::SuiteLoader},
+    progress_println,
+    reporter::results_reporter::ResultsReporter,
+    suite::{report::TestResultsReport, setup::Processes},
+    Suite,
+};
+
+use super::{
+    run_config::RunConfig,
+    runner::{RunSetup, Runner},
+    variable_check::VariableCheck,
+};
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long to wait after a change for an editor to finish writing, so that one save re-runs
+/// the suites once.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Clears the terminal and moves the cursor to the top.
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[3J\x1B[H";
+
+/// The last modification time of each watched file.
+type Snapshot = HashMap<PathBuf, SystemTime>;
+
+/// Runs the suites, then keeps running them again as their files change. Only suites whose files
+/// changed are run again, while the config's setup keeps running between runs. Changes to the
+/// config file, or to the env, variable, or template files it uses, run everything again from
+/// the setup.
+pub struct Watch {
+    path: PathBuf,
+    max_concurrency: Option<usize>,
+    strict: bool,
+    /// Started before the first run of a config, and finished when the config changes.
+    setup: Option<RunSetup>,
+    snapshot: Snapshot,
+    /// The name of the suite loaded from each file when it last ran, and whether it failed.
+    results: HashMap<PathBuf, (String, bool)>,
+    /// The background processes started by the setup of the suite in each file, which keep
+    /// running until the file changes or is removed.
+    processes: HashMap<PathBuf, Arc<Processes>>,
+    /// Set when the suites couldn't all be run, so the next change runs every suite.
+    pending: bool,
+}
+
+impl Watch {
+    pub fn new(path: PathBuf, max_concurrency: Option<usize>, strict: bool) -> Self {
+        Watch {
+            path,
+            max_concurrency,
+            strict,
+            setup: None,
+            snapshot: Snapshot::new(),
+            results: HashMap::new(),
+            processes: HashMap::new(),
+            pending: false,
+        }
+    }
+
+    /// Runs until the process is interrupted with Ctrl-C.
+    pub async fn run(&mut self) {
+        self.snapshot = self.take_snapshot();
+        self.run_all().await;
+
+        loop {
+            let changed = self.wait_for_changes().await;
+            let config_files = RunConfig::global().files();
+
+            progress_println!("{}", CLEAR_SCREEN);
+
+            if changed.iter().any(|path| config_files.contains(path)) {
+                progress_println!("Configuration changed, restarting setup.");
+                self.restart().await;
+                continue;
+            }
+
+            for path in changed.iter() {
+                if let Some(processes) = self.processes.remove(path) {
+                    processes.terminate().await;
+                }
+            }
+
+            let loader = SuiteLoader::new(&self.path);
+            let suite_files = loader.suite_files();
+
+            // Suites that include a changed file run again along with it
+            let canonical = changed
+                .iter()
+                .map(|path| std::fs::canonicalize(path).unwrap_or(path.clone()))
+                .collect::<Vec<PathBuf>>();
+            let includes = suite_files
+                .iter()
+                .map(|path| (path, included_files(path)))
+                .collect::<Vec<(&PathBuf, Vec<PathBuf>)>>();
+            let including = includes
+                .iter()
+                .filter(|(_, files)| canonical.iter().any(|path| files.contains(path)))
+                .map(|(path, _)| (*path).clone())
+                .collect::<Vec<PathBuf>>();
+
+            // Other YAML files, like test data, may be used by any suite
+            let shared = changed
+                .iter()
+                .zip(canonical.iter())
+                .any(|(path, canonical)| {
+                    !self.results.contains_key(path)
+                        && !includes.iter().any(|(_, files)| files.contains(canonical))
+                        && (!suite_files.contains(path) || !is_suite_file(path))
+                });
+
+            if self.pending || shared {
+                self.run_all().await;
+                continue;
+            }
+
+            for path in changed.iter() {
+                match self.results.remove(path) {
+                    Some((suite, _)) if !suite_files.contains(path) => {
+                        progress_println!("Suite '{}' was removed.", suite)
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut paths = changed
+                .into_iter()
+                .filter(|path| suite_files.contains(path))
+                .collect::<Vec<PathBuf>>();
+            for path in including {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+
+            let (files, suites) = load_suites(paths);
+            match suites.is_empty() {
+                true => self.print_watching(),
+                false => self.run_suites(files, suites).await,
+            }
+        }
+    }
+
+    /// Loads and runs every suite, checking their variables first.
+    async fn run_all(&mut self) {
+        self.results.clear();
+        self.pending = false;
+
+        let loader = SuiteLoader::new(&self.path);
+        let files = loader
+            .suite_files()
+            .into_iter()
+            .filter(|path| !RunConfig::global().files().contains(path))
+            .collect::<Vec<PathBuf>>();
+
+        let (files, suites) = load_suites(files);
+        progress_println!("Found and loaded {} test suites.", suites.len());
+
+        let variable_check = VariableCheck::new(&suites);
+        variable_check.print_report(self.strict);
+        if self.strict && variable_check.has_errors() {
+            self.pending = true;
+            self.print_watching();
+            return;
+        }
+
+        self.run_suites(files, suites).await;
+    }
+
+    /// Runs the suites loaded from the given files, starting the setup first if it isn't
+    /// running. Suites that ran before count as completed dependencies.
+    async fn run_suites(&mut self, files: Vec<PathBuf>, suites: Vec<Suite>) {
+        let names = suites
+            .iter()
+            .map(|suite| suite.suite.clone())
+            .collect::<HashSet<String>>();
+        let previous = self
+            .results
+            .values()
+            .filter(|(suite, _)| !names.contains(suite))
+            .cloned()
+            .collect::<HashMap<String, bool>>();
+
+        if self.setup.is_none() {
+            self.setup = Some(RunSetup::start().await);
+        }
+        let setup = self.setup.as_mut().expect("Setup was just started.");
+
+        let processes = files
+            .iter()
+            .map(|path| self.processes.entry(path.clone()).or_default().clone())
+            .collect::<Vec<Arc<Processes>>>();
+
+        let mut runner = Runner::new(suites, self.max_concurrency).keep_processes(processes);
+        let reports = runner.run_with(setup, &previous).await;
+
+        for (path, report) in files.into_iter().zip(reports.iter()) {
+            self.results
+                .insert(path, (report.suite.clone(), has_failures(report)));
+        }
+
+        let reporter = ResultsReporter::new(reports);
+        reporter.print_results();
+        reporter.print_summary();
+
+        self.print_watching();
+    }
+
+    /// Loads the config again, then finishes the setup of the previous config and runs
+    /// everything. If the config can't be loaded, the previous config and its setup are kept.
+    async fn restart(&mut self) {
+        if let Err(e) = RunConfig::reload() {
+            progress_println!("{}: {}", "ERROR".red(), e);
+            progress_println!("Keeping the previous configuration.");
+            self.print_watching();
+            return;
+        }
+
+        if let Some(setup) = self.setup.take() {
+            let failed = self.results.values().any(|(_, failed)| *failed);
+            setup.finish(failed).await;
+        }
+
+        // The config may use different files now
+        self.snapshot = self.take_snapshot();
+        self.run_all().await;
+    }
+
+    /// Waits until any watched file is added, changed, or removed, and returns those files.
+    async fn wait_for_changes(&mut self) -> Vec<PathBuf> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            if changed_files(&self.snapshot, &self.take_snapshot()).is_empty() {
+                continue;
+            }
+
+            tokio::time::sleep(SETTLE_TIME).await;
+            let snapshot = self.take_snapshot();
+            let changed = changed_files(&self.snapshot, &snapshot);
+            self.snapshot = snapshot;
+
+            return changed;
+        }
+    }
+
+    /// Records the modification time of the YAML files in the tests directory, and of the
+    /// files the config was loaded from.
+    fn take_snapshot(&self) -> Snapshot {
+        SuiteLoader::new(&self.path)
+            .suite_files()
+            .into_iter()
+            .chain(RunConfig::global().files())
+            .filter_map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, modified))
+            })
+            .collect()
+    }
+
+    fn print_watching(&self) {
+        progress_println!(
+            "{} Watching {:?} for changes. Press Ctrl-C to stop.",
+            "→".cyan(),
+            &self.path
+        );
+    }
+}
+
+/// Loads the suite in each file, returning the files that loaded along with their suites.
+fn load_suites(files: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<Suite>) {
+    files
+        .into_iter()
+        .filter_map(|path| SuiteLoader::load_suite(&path).map(|suite| (path, suite)))
+        .unzip()
+}
+
+fn has_failures(report: &TestResultsReport) -> bool {
+    report.failed > 0 || report.errors > 0
+}
+
+/// Returns the files that were added, changed, or removed between two snapshots.
+fn changed_files(before: &Snapshot, after: &Snapshot) -> Vec<PathBuf> {
+    let mut changed = after
+        .iter()
+        .filter(|(path, modified)| before.get(*path) != Some(*modified))
+        .map(|(path, _)| path.clone())
+        .chain(
+            before
+                .keys()
+                .filter(|path| !after.contains_key(*path))
+                .cloned(),
+        )
+        .collect::<Vec<PathBuf>>();
+
+    changed.sort();
+    changed
+}
+
+/// Returns false for YAML files that aren't suites, like files included by other suites.
+/// Files that can't be parsed are treated as suites, so that the error is reported.
+fn is_suite_file(path: &Path) -> bool {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return true,
+    };
+
+    match serde_yaml::from_str::<Value>(&contents) {
+        Ok(value) => value.get("suite").is_some(),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_changed_files() {
+        let earlier = SystemTime::UNIX_EPOCH;
+        let later = earlier + Duration::from_secs(1);
+
+        let before = Snapshot::from([
+            (PathBuf::from("a.yaml"), earlier),
+            (PathBuf::from("b.yaml"), earlier),
+            (PathBuf::from("c.yaml"), earlier),
+        ]);
+        let after = Snapshot::from([
+            (PathBuf::from("a.yaml"), earlier),
+            (PathBuf::from("b.yaml"), later),
+            (PathBuf::from("d.yaml"), earlier),
+        ]);
+
+        assert_eq!(
+            changed_files(&before, &after),
+            vec![
+                PathBuf::from("b.yaml"),
+                PathBuf::from("c.yaml"),
+                PathBuf::from("d.yaml")
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::{fmt, time::UNIX_EPOCH};
+
+use reqwest::header::SET_COOKIE;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Number;
+
+use crate::{
+    errors::CaptiError,
+    m_value::{m_map::MMap, m_match::MMatch, m_value::MValue, match_context::MatchContext},
+    variables::{variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences},
+};
+
+/// Cookies keyed by name. In a response, each cookie is a mapping of its parsed `Set-Cookie`
+/// attributes. In an expectation, each cookie is either a matcher for the cookie's value or a
+/// mapping of matchers for its attributes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct MCookies(MMap);
+
+impl MCookies {
+    /// Parses the cookies set by the response's `Set-Cookie` headers.
+    pub fn from_response(response: &reqwest::Response) -> Self {
+        let same_site = response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .filter_map(parse_same_site)
+            .collect::<Vec<(String, String)>>();
+
+        let cookies = response
+            .cookies()
+            .map(|cookie| {
+                let mut attributes = MMap::new();
+                attributes.insert(
+                    MValue::String("value".into()),
+                    MValue::String(cookie.value().into()),
+                );
+                if let Some(domain) = cookie.domain() {
+                    attributes.insert(
+                        MValue::String("domain".into()),
+                        MValue::String(domain.into()),
+                    );
+                }
+                if let Some(path) = cookie.path() {
+                    attributes.insert(MValue::String("path".into()), MValue::String(path.into()));
+                }
+                attributes.insert(
+                    MValue::String("secure".into()),
+                    MValue::Bool(cookie.secure()),
+                );
+                attributes.insert(
+                    MValue::String("httpOnly".into()),
+                    MValue::Bool(cookie.http_only()),
+                );
+                if let Some((_, value)) = same_site.iter().find(|(name, _)| name == cookie.name()) {
+                    attributes.insert(
+                        MValue::String("sameSite".into()),
+                        MValue::String(value.clone()),
+                    );
+                }
+                if let Some(expires) = cookie.expires() {
+                    let timestamp = match expires.duration_since(UNIX_EPOCH) {
+                        Ok(duration) => duration.as_secs() as i64,
+                        Err(e) => -(e.duration().as_secs() as i64),
+                    };
+                    attributes.insert(
+                        MValue::String("expires".into()),
+                        MValue::Number(Number::from(timestamp)),
+                    );
+                }
+                if let Some(max_age) = cookie.max_age() {
+                    attributes.insert(
+                        MValue::String("maxAge".into()),
+                        MValue::Number(Number::from(max_age.as_secs())),
+                    );
+                }
+
+                (
+                    MValue::String(cookie.name().into()),
+                    MValue::Mapping(attributes),
+                )
+            })
+            .collect::<MMap>();
+
+        MCookies(cookies)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the `name=value` pairs to send in a `Cookie` request header.
+    pub fn as_pairs(&self) -> Result<Vec<String>, CaptiError> {
+        self.0
+            .iter()
+            .map(|(name, value)| match (name, value) {
+                (MValue::String(name), MValue::String(value)) => Ok(format!("{}={}", name, value)),
+                (MValue::String(name), MValue::Number(value)) => Ok(format!("{}={}", name, value)),
+                (MValue::String(name), MValue::Bool(value)) => Ok(format!("{}={}", name, value)),
+                _ => Err(CaptiError::HTTPHeaderError(format!(
+                    "Invalid cookie:\n  {}: {}\nCookies must be string values.\n",
+                    &name, &value
+                ))),
+            })
+            .collect()
+    }
+
+    /// Expectations for a single value are matched against the cookie's value, and mappings are
+    /// matched against the cookie's attributes. Missing cookies are matched as null.
+    fn expected_and_found(&self, other: &Self) -> Vec<(MValue, MValue, MValue)> {
+        self.0
+            .iter()
+            .map(|(name, expected)| {
+                let cookie = other.0.get(name);
+                let found = match (expected, cookie) {
+                    (MValue::Mapping(_), Some(cookie)) => cookie.clone(),
+                    (_, Some(MValue::Mapping(attributes))) => attributes
+                        .get(&MValue::String("value".into()))
+                        .cloned()
+                        .unwrap_or(MValue::Null),
+                    _ => MValue::Null,
+                };
+                (name.clone(), expected.clone(), found)
+            })
+            .collect()
+    }
+}
+
+/// Reads the name and `SameSite` attribute of a raw `Set-Cookie` header.
+fn parse_same_site(header: &str) -> Option<(String, String)> {
+    let mut parts = header.split(';');
+    let name = parts.next()?.split('=').next()?.trim().to_string();
+    let same_site = parts.find_map(|attribute| {
+        let (key, value) = attribute.split_once('=')?;
+        match key.trim().eq_ignore_ascii_case("samesite") {
+            true => Some(value.trim().to_string()),
+            false => None,
+        }
+    })?;
+
+    Some((name, same_site))
+}
+
+impl MMatch for MCookies {
+    fn matches(&self, other: &Self) -> Result<bool, CaptiError> {
+        for (_, expected, found) in self.expected_and_found(other) {
+            if !expected.matches(&found)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn get_context(&self, other: &Self) -> MatchContext {
+        let mut context = MatchContext::new();
+
+        for (name, expected, found) in self.expected_and_found(other) {
+            match expected.matches(&found) {
+                Ok(true) => {}
+                Ok(false) => {
+                    context += expected.get_context(&found);
+                    context.push(format!("Mismatch at cookie {}:", &name));
+                }
+                Err(e) => {
+                    context.push(format!("Matching error at cookie {}: {}", &name, e));
+                }
+            }
+        }
+
+        context
+    }
+}
+
+impl SuiteVariables for MCookies {
+    fn populate_variables(&mut self, variables: &mut VariableMap) -> Result<(), CaptiError> {
+        self.0.populate_variables(variables)
+    }
+}
+
+impl VariableReferences for MCookies {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.0.variable_references(field)
+    }
+}
+
+impl fmt::Display for MCookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cookies(yaml: &str) -> MCookies {
+        serde_yaml::from_str::<MCookies>(yaml).unwrap()
+    }
+
+    #[test]
+    fn matches_values_and_attributes() {
+        let response = cookies(
+            r#"
+            session:
+              value: abc123
+              path: /
+              secure: true
+              httpOnly: true
+              sameSite: Strict
+            "#,
+        );
+
+        assert!(cookies("session: abc123").matches(&response).unwrap());
+        assert!(cookies("session: $exists").matches(&response).unwrap());
+        assert!(cookies("other: $absent").matches(&response).unwrap());
+        assert!(cookies("session: { httpOnly: true, sameSite: Strict }")
+            .matches(&response)
+            .unwrap());
+
+        assert!(!cookies("session: other").matches(&response).unwrap());
+        assert!(!cookies("session: { secure: false }")
+            .matches(&response)
+            .unwrap());
+        assert!(!cookies("other: $exists").matches(&response).unwrap());
+    }
+
+    #[test]
+    fn parses_same_site() {
+        assert_eq!(
+            parse_same_site("session=abc; Path=/; SameSite=Lax; HttpOnly"),
+            Some((String::from("session"), String::from("Lax")))
+        );
+        assert_eq!(parse_same_site("session=abc; Path=/"), None);
+    }
+}
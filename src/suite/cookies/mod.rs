@@ -0,0 +1,3 @@
+pub mod m_cookies;
+
+pub use m_cookies::MCookies;
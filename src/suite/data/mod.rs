@@ -0,0 +1,3 @@
+pub mod test_data;
+
+pub use test_data::TestData;
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    errors::CaptiError,
+    m_value::{m_value::MValue, mvalue_wrapper::MValueWrapper},
+    variables::variable_map::VariableMap,
+};
+
+/// The data set used to expand a single test definition into multiple test instances. Each entry
+/// is a set of variables that are applied to one instance of the test. The data can be defined
+/// inline as a sequence of variable mappings, or as a path to a CSV, JSON, or YAML file relative
+/// to the suite file.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum TestData {
+    Inline(Vec<VariableMap>),
+    File(PathBuf),
+}
+
+impl TestData {
+    pub fn load(&self, base_dir: &Path) -> Result<Vec<VariableMap>, CaptiError> {
+        match self {
+            TestData::Inline(rows) => Ok(rows.clone()),
+            TestData::File(path) => {
+                let path = base_dir.join(path);
+                let contents = std::fs::read_to_string(&path).map_err(|e| {
+                    CaptiError::test_data_error(format!(
+                        "Unable to read test data file {:?}: {}",
+                        &path, e
+                    ))
+                })?;
+
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("csv") => parse_csv(&contents),
+                    Some("json") => serde_json::from_str::<Vec<VariableMap>>(&contents)
+                        .map_err(|e| {
+                            CaptiError::test_data_error(format!(
+                                "Unable to parse JSON test data file {:?}: {}",
+                                &path, e
+                            ))
+                        }),
+                    Some("yaml") | Some("yml") => {
+                        serde_yaml::from_str::<Vec<VariableMap>>(&contents).map_err(|e| {
+                            CaptiError::test_data_error(format!(
+                                "Unable to parse YAML test data file {:?}: {}",
+                                &path, e
+                            ))
+                        })
+                    }
+                    _ => Err(CaptiError::test_data_error(format!(
+                        "Unsupported test data file {:?}. Test data must be a .csv, .json, .yaml, or .yml file.",
+                        &path
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+/// Parses CSV contents with a header row, where each header is a variable name and each
+/// following record is one set of variables. Cells are interpreted as JSON values where possible
+/// so that numbers and booleans keep their types.
+fn parse_csv(contents: &str) -> Result<Vec<VariableMap>, CaptiError> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+
+    let headers = reader
+        .headers()
+        .map_err(|e| CaptiError::test_data_error(format!("Invalid CSV header row: {}", e)))?
+        .clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record
+            .map_err(|e| CaptiError::test_data_error(format!("Invalid CSV record: {}", e)))?;
+
+        let mut variables = VariableMap::new();
+        for (key, value) in headers.iter().zip(record.iter()) {
+            let value = match value {
+                "" => MValue::String(String::new()),
+                value => MValueWrapper::from_json_value(value),
+            };
+            variables.insert(key.trim(), value);
+        }
+
+        rows.push(variables);
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_csv_rows_into_variables() {
+        let contents = "EMAIL,STATUS\ninvalid,400\n,422\n";
+        let rows = parse_csv(contents).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["EMAIL"], MValue::String("invalid".into()));
+        assert_eq!(rows[0]["STATUS"], MValue::Number(400.into()));
+        assert_eq!(rows[1]["EMAIL"], MValue::String("".into()));
+    }
+
+    #[test]
+    fn deserializes_inline_data() {
+        let yaml = r#"
+        - EMAIL: invalid
+        - EMAIL: ""
+        "#;
+
+        let data = serde_yaml::from_str::<TestData>(yaml).unwrap();
+        let rows = data.load(Path::new(".")).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["EMAIL"], MValue::String("invalid".into()));
+    }
+
+    #[test]
+    fn deserializes_file_path() {
+        let data = serde_yaml::from_str::<TestData>("data/users.csv").unwrap();
+        assert_eq!(data, TestData::File(PathBuf::from("data/users.csv")));
+    }
+}
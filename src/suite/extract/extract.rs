@@ -1,10 +1,13 @@
+use std::collections::HashSet;
+
 use serde::Deserialize;
 
 use crate::{
     errors::CaptiError,
     m_value::m_value::MValue,
+    progress_println,
     suite::{headers::MHeaders, response::ResponseDefinition},
-    variables::variable_map::VariableMap,
+    variables::{variable_map::VariableMap, VariableReferences},
 };
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
@@ -15,6 +18,17 @@ pub struct ResponseExtractor {
 }
 
 impl ResponseExtractor {
+    /// Returns the names of every variable this extractor assigns.
+    pub fn variable_names(&self) -> HashSet<String> {
+        let mut references = self.body.variable_references("extract.body");
+        references.extend(self.headers.variable_references("extract.headers"));
+
+        references
+            .into_iter()
+            .map(|reference| reference.name)
+            .collect()
+    }
+
     pub async fn extract(
         &self,
         response: &ResponseDefinition,
@@ -82,12 +96,21 @@ fn body_extract(
                 ))
             })?;
         }
-        (left, right) => {
-            return Err(CaptiError::extract_error(format!(
-                "Variable extraction failed - cannot compare '{}' with '{}' - invalid type.",
-                &left, &right
-            )))
-        }
+        (MValue::String(extractor), right) => match variables.whole_variable(extractor) {
+            Some(name) => {
+                progress_println!("Extracted variable {}: {}", name, right);
+                variables.insert(name, right.clone());
+            }
+            None => return Err(invalid_type(left, right)),
+        },
+        (left, right) => return Err(invalid_type(left, right)),
     }
     Ok(())
 }
+
+fn invalid_type(left: &MValue, right: &MValue) -> CaptiError {
+    CaptiError::extract_error(format!(
+        "Variable extraction failed - cannot compare '{}' with '{}' - invalid type.",
+        left, right
+    ))
+}
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     errors::CaptiError,
     m_value::{m_map::MMap, m_match::MMatch, m_value::MValue, match_context::MatchContext},
-    variables::{variable_map::VariableMap, SuiteVariables},
+    variables::{variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -22,6 +22,12 @@ impl SuiteVariables for MHeaders {
     }
 }
 
+impl VariableReferences for MHeaders {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.0.variable_references(field)
+    }
+}
+
 impl MMatch for MHeaders {
     fn matches(&self, other: &Self) -> Result<bool, CaptiError> {
         let lowercase_headers = self
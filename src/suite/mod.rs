@@ -1,3 +1,5 @@
+pub mod cookies;
+pub mod data;
 pub mod extract;
 pub mod failure_report;
 pub mod headers;
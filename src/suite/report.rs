@@ -13,6 +13,8 @@ pub struct TestResultsReport {
     pub failed: usize,
     pub errors: usize,
     pub results: Vec<ReportedResult>,
+    /// Setup hooks that failed after the suite's tests ran, by hook name.
+    pub setup_errors: Vec<(String, CaptiError)>,
 }
 
 pub struct ReportedResult {
@@ -65,8 +67,15 @@ impl TestResultsReport {
             failed,
             errors,
             results: tests,
+            setup_errors: Vec::new(),
         }
     }
+
+    /// Records a setup hook that failed after the tests ran, counting it as an error.
+    pub fn add_setup_error(&mut self, hook: impl Into<String>, error: CaptiError) {
+        self.errors += 1;
+        self.setup_errors.push((hook.into(), error));
+    }
 }
 
 impl fmt::Display for TestResultsReport {
@@ -79,6 +88,10 @@ impl fmt::Display for TestResultsReport {
             writeln!(f, "{}", result)?;
         }
 
+        for (hook, _) in &self.setup_errors {
+            writeln!(f, "{} {}", "⚠".yellow(), hook)?;
+        }
+
         writeln!(f, " ")?;
 
         let passed = {
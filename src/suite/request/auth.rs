@@ -0,0 +1,166 @@
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{oauth2, Client},
+    errors::CaptiError,
+    variables::{
+        secrets, variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences,
+    },
+};
+
+/// Authentication applied to a request. Digest authentication is applied in response to the
+/// server's challenge, so it is handled when the request is sent.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    ApiKey {
+        header: Option<String>,
+        query: Option<String>,
+        value: String,
+    },
+    Digest {
+        username: String,
+        password: String,
+    },
+    #[serde(rename = "oauth2")]
+    OAuth2(String),
+}
+
+impl Auth {
+    /// Adds the credentials to the request.
+    pub async fn apply(
+        &self,
+        request_builder: RequestBuilder,
+        client: &Client,
+    ) -> Result<RequestBuilder, CaptiError> {
+        for secret in self.secrets() {
+            secrets::register(secret);
+        }
+
+        let request_builder = match self {
+            Auth::Basic { username, password } => {
+                request_builder.basic_auth(username, password.as_ref())
+            }
+            Auth::Bearer(token) => request_builder.bearer_auth(token),
+            Auth::ApiKey {
+                header: Some(header),
+                query: None,
+                value,
+            } => request_builder.header(header, value),
+            Auth::ApiKey {
+                header: None,
+                query: Some(query),
+                value,
+            } => request_builder.query(&[(query, value)]),
+            Auth::ApiKey { .. } => {
+                return Err(CaptiError::auth_error(
+                    "API key authentication requires exactly one of 'header' or 'query'.",
+                ))
+            }
+            Auth::Digest { .. } => request_builder,
+            Auth::OAuth2(provider) => {
+                let token = oauth2::access_token(provider, client).await?;
+                request_builder.bearer_auth(token)
+            }
+        };
+
+        Ok(request_builder)
+    }
+
+    /// The credentials that must be masked in output.
+    fn secrets(&self) -> Vec<&str> {
+        match self {
+            Auth::Basic { password, .. } => password.iter().map(String::as_str).collect(),
+            Auth::Bearer(token) => vec![token],
+            Auth::ApiKey { value, .. } => vec![value],
+            Auth::Digest { password, .. } => vec![password],
+            Auth::OAuth2(_) => vec![],
+        }
+    }
+}
+
+impl SuiteVariables for Auth {
+    fn populate_variables(&mut self, variables: &mut VariableMap) -> Result<(), CaptiError> {
+        let fields = match self {
+            Auth::Basic { username, password } => {
+                let mut fields = vec![username];
+                fields.extend(password.as_mut());
+                fields
+            }
+            Auth::Bearer(token) => vec![token],
+            Auth::ApiKey { value, .. } => vec![value],
+            Auth::Digest { username, password } => vec![username, password],
+            Auth::OAuth2(_) => vec![],
+        };
+
+        for field in fields {
+            *field = variables.replace_variables(field.as_str())?.into();
+        }
+
+        Ok(())
+    }
+}
+
+impl VariableReferences for Auth {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        let fields = match self {
+            Auth::Basic { username, password } => {
+                let mut fields = vec![("basic.username", username)];
+                fields.extend(password.iter().map(|password| ("basic.password", password)));
+                fields
+            }
+            Auth::Bearer(token) => vec![("bearer", token)],
+            Auth::ApiKey { value, .. } => vec![("api_key.value", value)],
+            Auth::Digest { username, password } => {
+                vec![("digest.username", username), ("digest.password", password)]
+            }
+            Auth::OAuth2(_) => vec![],
+        };
+
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| value.variable_references(&format!("{}.{}", field, name)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(yaml: &str) -> Auth {
+        serde_yaml::with::singleton_map::deserialize(serde_yaml::Deserializer::from_str(yaml))
+            .unwrap()
+    }
+
+    #[test]
+    fn deserializes_auth() {
+        let auth = parse("bearer: ${TOKEN}");
+        assert_eq!(auth, Auth::Bearer(String::from("${TOKEN}")));
+
+        let auth = parse(
+            r#"
+            api_key:
+              header: X-API-Key
+              value: abc123
+            "#,
+        );
+        assert_eq!(
+            auth,
+            Auth::ApiKey {
+                header: Some(String::from("X-API-Key")),
+                query: None,
+                value: String::from("abc123"),
+            }
+        );
+
+        let auth = parse("oauth2: internal");
+        assert_eq!(auth, Auth::OAuth2(String::from("internal")));
+    }
+}
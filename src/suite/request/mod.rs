@@ -1,5 +1,7 @@
+pub mod auth;
 mod query_params;
 pub mod request_definition;
 pub mod request_method;
+pub mod sign;
 
 pub use request_definition::RequestDefinition;
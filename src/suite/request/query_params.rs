@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     errors::CaptiError,
     m_value::m_value::MValue,
-    variables::{variable_map::VariableMap, SuiteVariables},
+    variables::{variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -34,6 +34,15 @@ impl SuiteVariables for QueryParams {
     }
 }
 
+impl VariableReferences for QueryParams {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.0
+            .iter()
+            .flat_map(|(key, value)| value.variable_references(&format!("{}.{}", field, key)))
+            .collect()
+    }
+}
+
 impl Deref for QueryParams {
     type Target = HashMap<String, String>;
     fn deref(&self) -> &Self::Target {
@@ -1,14 +1,23 @@
-use reqwest::{header::HeaderMap, RequestBuilder};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE,
+    },
+    Request, RequestBuilder, Response, StatusCode, Url,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    client::{digest::DigestChallenge, Client},
     errors::CaptiError,
     m_value::m_value::MValue,
-    suite::headers::MHeaders,
-    variables::{variable_map::VariableMap, SuiteVariables},
+    runner::run_config::RunConfig,
+    suite::{cookies::MCookies, headers::MHeaders, response::ResponseDefinition},
+    variables::{
+        secrets, variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences,
+    },
 };
 
-use super::{query_params::QueryParams, request_method::RequestMethod};
+use super::{auth::Auth, query_params::QueryParams, request_method::RequestMethod, sign::Signing};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RequestDefinition {
@@ -18,25 +27,98 @@ pub struct RequestDefinition {
     params: QueryParams,
     #[serde(default)]
     headers: MHeaders,
+    #[serde(default)]
+    cookies: MCookies,
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    auth: Option<Auth>,
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    sign: Option<Signing>,
     body: Option<MValue>,
 }
 
 impl RequestDefinition {
+    /// Describes the request by its method and url.
+    pub fn summary(&self) -> String {
+        format!("{} {}", self.method.as_str(), &self.url)
+    }
+
+    /// Sends the request, following redirects and answering a digest challenge if the server
+    /// sends one.
+    pub async fn send(&self, client: &Client) -> Result<ResponseDefinition, CaptiError> {
+        let request = self.build_request(client, true, None).await?;
+        let response = client.execute(request).await?;
+
+        let response = match self.digest_authorization(&response)? {
+            Some(authorization) => {
+                let request = self
+                    .build_request(client, true, Some(authorization))
+                    .await?;
+                client.execute(request).await?
+            }
+            None => response,
+        };
+
+        Ok(ResponseDefinition::from_response(response).await)
+    }
+
+    /// Builds the request with its credentials and signature. The `Authorization` header answering
+    /// a digest challenge is added if provided.
+    pub async fn build_request(
+        &self,
+        client: &Client,
+        follow_redirects: bool,
+        authorization: Option<String>,
+    ) -> Result<Request, CaptiError> {
+        let request = self.build_client_request(client, follow_redirects)?;
+        let mut request = self.authorize(request, client).await?;
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+
+        let mut request = request.build()?;
+        self.sign(&mut request)?;
+
+        for header in [AUTHORIZATION, PROXY_AUTHORIZATION] {
+            if let Some(value) = request.headers().get(header).and_then(|v| v.to_str().ok()) {
+                secrets::register_credentials(value);
+            }
+        }
+
+        Ok(request)
+    }
+
     pub fn build_client_request(
         &self,
-        client: &reqwest::Client,
+        client: &Client,
+        follow_redirects: bool,
     ) -> Result<RequestBuilder, CaptiError> {
-        let url = format!("{}{}", &self.url, &self.params.as_query_string());
+        let url = match &RunConfig::global().base_url {
+            Some(base_url) if self.url.starts_with('/') => {
+                format!("{}{}", base_url.trim_end_matches('/'), &self.url)
+            }
+            _ => self.url.clone(),
+        };
+        let url = format!("{}{}", url, &self.params.as_query_string());
+
+        let mut header_map = TryInto::<HeaderMap>::try_into(&self.headers)?;
+        let cookie_header = self.cookie_header(client, &url, header_map.get(COOKIE))?;
+        if let Some(cookie_header) = cookie_header {
+            header_map.insert(COOKIE, cookie_header);
+        }
+
+        let http_client = match follow_redirects {
+            true => client,
+            false => client.without_redirects(),
+        };
 
         let mut request_builder = match self.method {
-            RequestMethod::Get => client.get(url),
-            RequestMethod::Post => client.post(url),
-            RequestMethod::Patch => client.patch(url),
-            RequestMethod::Put => client.put(url),
-            RequestMethod::Delete => client.delete(url),
+            RequestMethod::Get => http_client.get(url),
+            RequestMethod::Post => http_client.post(url),
+            RequestMethod::Patch => http_client.patch(url),
+            RequestMethod::Put => http_client.put(url),
+            RequestMethod::Delete => http_client.delete(url),
         };
 
-        let header_map = TryInto::<HeaderMap>::try_into(&self.headers)?;
         request_builder = request_builder.headers(header_map);
 
         if let Some(body) = &self.body {
@@ -46,6 +128,121 @@ impl RequestDefinition {
 
         Ok(request_builder)
     }
+
+    /// Adds the request's credentials, if any, to the request.
+    pub async fn authorize(
+        &self,
+        request_builder: RequestBuilder,
+        client: &Client,
+    ) -> Result<RequestBuilder, CaptiError> {
+        match &self.auth {
+            Some(auth) => auth.apply(request_builder, client).await,
+            None => Ok(request_builder),
+        }
+    }
+
+    /// Signs the built request, if the request defines a signing method.
+    pub fn sign(&self, request: &mut Request) -> Result<(), CaptiError> {
+        match &self.sign {
+            Some(signing) => signing.sign(request),
+            None => Ok(()),
+        }
+    }
+
+    /// Answers the digest challenge in an unauthorized response, returning the `Authorization`
+    /// header to retry the request with. Returns `None` unless the request uses digest
+    /// authentication and the response contains a digest challenge.
+    pub fn digest_authorization(&self, response: &Response) -> Result<Option<String>, CaptiError> {
+        let (username, password) = match &self.auth {
+            Some(Auth::Digest { username, password }) => (username, password),
+            _ => return Ok(None),
+        };
+
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(None);
+        }
+
+        let challenge = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .find_map(|header| DigestChallenge::parse(header).transpose());
+
+        let challenge = match challenge {
+            Some(challenge) => challenge?,
+            None => return Ok(None),
+        };
+
+        let url = response.url();
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        Ok(Some(challenge.authorization(
+            username,
+            password,
+            self.method.as_str(),
+            &uri,
+        )))
+    }
+
+    /// Setting a `Cookie` header prevents the client from adding its stored cookies, so the
+    /// request's cookies are combined with any `Cookie` header written in the request and the
+    /// stored cookies for the url.
+    fn cookie_header(
+        &self,
+        client: &Client,
+        url: &str,
+        header: Option<&HeaderValue>,
+    ) -> Result<Option<HeaderValue>, CaptiError> {
+        if self.cookies.is_empty() {
+            return Ok(None);
+        }
+
+        let mut cookies = match header {
+            Some(header) => vec![header
+                .to_str()
+                .map_err(|_| {
+                    CaptiError::HTTPHeaderError(String::from("Invalid cookie header in request."))
+                })?
+                .to_string()],
+            None => Vec::new(),
+        };
+        if let Ok(url) = Url::parse(url) {
+            cookies.extend(client.cookies(&url));
+        }
+        cookies.extend(self.cookies.as_pairs()?);
+
+        let header = cookies.join("; ");
+        HeaderValue::from_str(&header).map(Some).map_err(|_| {
+            CaptiError::HTTPHeaderError(format!("Invalid cookie header:\n  {}", header))
+        })
+    }
+}
+
+impl VariableReferences for RequestDefinition {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        let mut references = self.url.variable_references(&format!("{}.url", field));
+        references.extend(
+            self.params
+                .variable_references(&format!("{}.params", field)),
+        );
+        references.extend(
+            self.headers
+                .variable_references(&format!("{}.headers", field)),
+        );
+        references.extend(
+            self.cookies
+                .variable_references(&format!("{}.cookies", field)),
+        );
+        references.extend(self.auth.variable_references(&format!("{}.auth", field)));
+        references.extend(self.sign.variable_references(&format!("{}.sign", field)));
+        references.extend(self.body.variable_references(&format!("{}.body", field)));
+
+        references
+    }
 }
 
 impl SuiteVariables for RequestDefinition {
@@ -53,8 +250,42 @@ impl SuiteVariables for RequestDefinition {
         self.url = variables.replace_variables(&self.url)?.into();
         self.params.populate_variables(variables)?;
         self.headers.populate_variables(variables)?;
+        self.cookies.populate_variables(variables)?;
+        self.auth.populate_variables(variables)?;
+        self.sign.populate_variables(variables)?;
         self.body.populate_variables(variables)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn combines_cookie_header_with_cookies() {
+        let request = serde_yaml::from_str::<RequestDefinition>(
+            r#"
+            method: GET
+            url: http://localhost:3000/recipes
+            headers:
+              Cookie: theme=dark
+            cookies:
+              session: abc123
+            "#,
+        )
+        .unwrap();
+
+        let request = request
+            .build_client_request(&Client::default(), true)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.headers().get(COOKIE).unwrap(),
+            "theme=dark; session=abc123"
+        );
+    }
+}
@@ -9,3 +9,15 @@ pub enum RequestMethod {
     Put,
     Delete,
 }
+
+impl RequestMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestMethod::Get => "GET",
+            RequestMethod::Post => "POST",
+            RequestMethod::Patch => "PATCH",
+            RequestMethod::Put => "PUT",
+            RequestMethod::Delete => "DELETE",
+        }
+    }
+}
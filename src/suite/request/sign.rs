@@ -0,0 +1,195 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::Request;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    client::signing::{self, AwsCredentials, HmacAlgorithm, SignatureEncoding},
+    errors::CaptiError,
+    variables::{
+        secrets, variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences,
+    },
+};
+
+/// Signs a request after it has been built, so the signature covers the final method, url,
+/// headers, and body.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Signing {
+    Hmac(HmacSigning),
+    AwsSigv4(AwsSigV4Signing),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HmacSigning {
+    secret: String,
+    #[serde(default = "default_signature_header")]
+    header: String,
+    #[serde(default)]
+    algorithm: HmacAlgorithm,
+    #[serde(default)]
+    encoding: SignatureEncoding,
+    #[serde(default)]
+    signed_headers: Vec<String>,
+    timestamp_header: Option<String>,
+}
+
+/// Credentials default to the standard AWS environment variables when they are not set.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AwsSigV4Signing {
+    service: String,
+    region: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    session_token: Option<String>,
+}
+
+fn default_signature_header() -> String {
+    String::from("X-Signature")
+}
+
+impl Signing {
+    pub fn sign(&self, request: &mut Request) -> Result<(), CaptiError> {
+        match self {
+            Signing::Hmac(hmac) => hmac.sign(request),
+            Signing::AwsSigv4(aws) => aws.sign(request),
+        }
+    }
+}
+
+impl HmacSigning {
+    fn sign(&self, request: &mut Request) -> Result<(), CaptiError> {
+        secrets::register(&self.secret);
+
+        let timestamp = match &self.timestamp_header {
+            Some(header) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default()
+                    .to_string();
+                signing::insert_header(request, header, &timestamp)?;
+                Some(timestamp)
+            }
+            None => None,
+        };
+
+        let string_to_sign =
+            signing::hmac_string_to_sign(request, &self.signed_headers, timestamp.as_deref());
+        let signature = signing::hmac_signature(
+            &self.secret,
+            &string_to_sign,
+            &self.algorithm,
+            &self.encoding,
+        )?;
+
+        signing::insert_header(request, &self.header, &signature)
+    }
+}
+
+impl AwsSigV4Signing {
+    fn sign(&self, request: &mut Request) -> Result<(), CaptiError> {
+        let region = self
+            .region
+            .clone()
+            .or(env_value("AWS_REGION"))
+            .or(env_value("AWS_DEFAULT_REGION"))
+            .unwrap_or(String::from("us-east-1"));
+
+        let access_key = self
+            .access_key
+            .clone()
+            .or(env_value("AWS_ACCESS_KEY_ID"))
+            .ok_or(CaptiError::auth_error(
+                "AWS signing requires an access_key or the AWS_ACCESS_KEY_ID environment variable.",
+            ))?;
+
+        let secret_key = self
+            .secret_key
+            .clone()
+            .or(env_value("AWS_SECRET_ACCESS_KEY"))
+            .ok_or(CaptiError::auth_error(
+                "AWS signing requires a secret_key or the AWS_SECRET_ACCESS_KEY environment variable.",
+            ))?;
+
+        let session_token = self
+            .session_token
+            .clone()
+            .or(env_value("AWS_SESSION_TOKEN"));
+
+        secrets::register(&secret_key);
+        if let Some(session_token) = &session_token {
+            secrets::register(session_token);
+        }
+
+        let credentials = AwsCredentials {
+            access_key: &access_key,
+            secret_key: &secret_key,
+            session_token: session_token.as_deref(),
+        };
+
+        signing::sign_aws_sigv4(
+            request,
+            &credentials,
+            &region,
+            &self.service,
+            OffsetDateTime::now_utc(),
+        )
+    }
+}
+
+/// Reads a variable from the environment or the env file in the config.
+fn env_value(name: &str) -> Option<String> {
+    VariableMap::new().get(name).map(|value| value.into())
+}
+
+impl SuiteVariables for Signing {
+    fn populate_variables(&mut self, variables: &mut VariableMap) -> Result<(), CaptiError> {
+        let fields = match self {
+            Signing::Hmac(hmac) => vec![&mut hmac.secret],
+            Signing::AwsSigv4(aws) => {
+                let mut fields = vec![&mut aws.service];
+                fields.extend(aws.region.as_mut());
+                fields.extend(aws.access_key.as_mut());
+                fields.extend(aws.secret_key.as_mut());
+                fields.extend(aws.session_token.as_mut());
+                fields
+            }
+        };
+
+        for field in fields {
+            *field = variables.replace_variables(field.as_str())?.into();
+        }
+
+        Ok(())
+    }
+}
+
+impl VariableReferences for Signing {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        match self {
+            Signing::Hmac(hmac) => hmac
+                .secret
+                .variable_references(&format!("{}.hmac.secret", field)),
+            Signing::AwsSigv4(aws) => {
+                let mut references = aws
+                    .region
+                    .variable_references(&format!("{}.aws_sigv4.region", field));
+                references.extend(
+                    aws.access_key
+                        .variable_references(&format!("{}.aws_sigv4.access_key", field)),
+                );
+                references.extend(
+                    aws.secret_key
+                        .variable_references(&format!("{}.aws_sigv4.secret_key", field)),
+                );
+                references.extend(
+                    aws.session_token
+                        .variable_references(&format!("{}.aws_sigv4.session_token", field)),
+                );
+                references
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
+pub mod redirect;
 pub mod response_definition;
 pub mod status;
 
+pub use redirect::{Redirect, Redirects};
 pub use response_definition::ResponseDefinition;
@@ -0,0 +1,187 @@
+use std::{fmt, ops::Deref};
+
+use serde::Deserialize;
+
+use crate::{
+    errors::CaptiError,
+    m_value::{m_match::MMatch, m_value::MValue, match_context::MatchContext},
+    variables::{variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences},
+};
+
+use super::status::Status;
+
+/// A single intermediate redirect response, recorded with its status and `Location` header.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Redirect {
+    pub status: Status,
+    #[serde(default)]
+    pub location: MValue,
+}
+
+impl Redirect {
+    pub fn new(status: u16, location: impl Into<String>) -> Self {
+        Redirect {
+            status: Status::from(status),
+            location: MValue::String(location.into()),
+        }
+    }
+}
+
+impl MMatch for Redirect {
+    fn matches(&self, other: &Self) -> Result<bool, CaptiError> {
+        Ok(self.status.matches(&other.status)? && self.location.matches(&other.location)?)
+    }
+
+    fn get_context(&self, other: &Self) -> MatchContext {
+        let mut context = MatchContext::new();
+        if let Ok(false) = self.status.matches(&other.status) {
+            context += self.status.get_context(&other.status);
+        }
+        if let Ok(false) = self.location.matches(&other.location) {
+            context += self.location.get_context(&other.location);
+        }
+        context
+    }
+}
+
+/// The sequence of redirects followed before the final response.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Redirects(Vec<Redirect>);
+
+impl MMatch for Redirects {
+    fn matches(&self, other: &Self) -> Result<bool, CaptiError> {
+        if self.len() != other.len() {
+            return Ok(false);
+        }
+
+        for (expected, found) in self.iter().zip(other.iter()) {
+            if !expected.matches(found)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn get_context(&self, other: &Self) -> MatchContext {
+        let mut context = MatchContext::new();
+
+        if self.len() != other.len() {
+            context.push(format!(
+                "Expected {} redirect(s), found {}.",
+                self.len(),
+                other.len()
+            ));
+            for redirect in other.iter() {
+                context.push(format!("  {}", redirect));
+            }
+            return context;
+        }
+
+        for (i, (expected, found)) in self.iter().zip(other.iter()).enumerate() {
+            if let Ok(false) = expected.matches(found) {
+                context.push(format!("Redirect {} does not match: {}", i + 1, found));
+                context += expected.get_context(found);
+                break;
+            }
+        }
+
+        context
+    }
+}
+
+impl SuiteVariables for Redirects {
+    fn populate_variables(&mut self, variables: &mut VariableMap) -> Result<(), CaptiError> {
+        for redirect in self.0.iter_mut() {
+            redirect.populate_variables(variables)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl VariableReferences for Redirects {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(i, redirect)| redirect.variable_references(&format!("{}[{}]", field, i)))
+            .collect()
+    }
+}
+
+impl Deref for Redirects {
+    type Target = Vec<Redirect>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<Redirect>> for Redirects {
+    fn from(redirects: Vec<Redirect>) -> Self {
+        Redirects(redirects)
+    }
+}
+
+impl fmt::Display for Redirects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redirects = self
+            .0
+            .iter()
+            .map(|redirect| redirect.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}", redirects.join("\n"))
+    }
+}
+
+impl SuiteVariables for Redirect {
+    fn populate_variables(&mut self, variables: &mut VariableMap) -> Result<(), CaptiError> {
+        self.location.populate_variables(variables)
+    }
+}
+
+impl VariableReferences for Redirect {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.location
+            .variable_references(&format!("{}.location", field))
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.status, self.location)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_redirect_chain() {
+        let expected = serde_yaml::from_str::<Redirects>(
+            r#"
+            - status: 301
+              location: /new
+            - status: 3xx
+            "#,
+        )
+        .unwrap();
+
+        let found = Redirects::from(vec![
+            Redirect::new(301, "/new"),
+            Redirect::new(302, "/login"),
+        ]);
+        assert!(expected.matches(&found).unwrap());
+
+        let too_short = Redirects::from(vec![Redirect::new(301, "/new")]);
+        assert!(!expected.matches(&too_short).unwrap());
+
+        let wrong_location = Redirects::from(vec![
+            Redirect::new(301, "/old"),
+            Redirect::new(302, "/login"),
+        ]);
+        assert!(!expected.matches(&wrong_location).unwrap());
+    }
+}
@@ -6,11 +6,11 @@ use crate::{
     errors::CaptiError,
     formatting::indent::Indent,
     m_value::{m_match::MMatch, m_value::MValue, status_matcher::StatusMatcher},
-    suite::{headers::MHeaders, test_result::TestResult},
-    variables::{variable_map::VariableMap, SuiteVariables},
+    suite::{cookies::MCookies, headers::MHeaders, test_result::TestResult},
+    variables::{variable_map::VariableMap, SuiteVariables, VariableReference, VariableReferences},
 };
 
-use super::status::Status;
+use super::{redirect::Redirects, status::Status};
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct ResponseDefinition {
@@ -19,6 +19,8 @@ pub struct ResponseDefinition {
     pub headers: MHeaders,
     #[serde(default)]
     pub body: MValue,
+    pub cookies: Option<MCookies>,
+    pub redirects: Option<Redirects>,
 }
 
 impl ResponseDefinition {
@@ -26,6 +28,7 @@ impl ResponseDefinition {
         let status = Status::from(StatusMatcher::Exact(response.status().as_u16()));
 
         let headers = MHeaders::from(response.headers());
+        let cookies = MCookies::from_response(&response);
 
         let body_text = response.text().await.unwrap_or("".to_string());
         let body = match serde_json::from_str::<MValue>(&body_text) {
@@ -37,6 +40,8 @@ impl ResponseDefinition {
             status,
             headers,
             body,
+            cookies: Some(cookies),
+            redirects: None,
         }
     }
 
@@ -54,6 +59,20 @@ impl ResponseDefinition {
             _ => {}
         }
 
+        if let Some(redirects) = &self.redirects {
+            let found = other.redirects.clone().unwrap_or_default();
+            match redirects.matches(&found) {
+                Ok(false) => {
+                    return Ok(TestResult::fail(
+                        "Redirects do not match.",
+                        redirects.get_context(&found),
+                    ));
+                }
+                Err(e) => return Err(e),
+                _ => {}
+            }
+        }
+
         match self.headers.matches(&other.headers) {
             Ok(false) => {
                 return Ok(TestResult::fail(
@@ -67,6 +86,20 @@ impl ResponseDefinition {
             _ => {}
         }
 
+        if let Some(cookies) = &self.cookies {
+            let found = other.cookies.clone().unwrap_or_default();
+            match cookies.matches(&found) {
+                Ok(false) => {
+                    return Ok(TestResult::fail(
+                        "Cookies do not match.",
+                        cookies.get_context(&found),
+                    ));
+                }
+                Err(e) => return Err(e),
+                _ => {}
+            }
+        }
+
         match self.body.matches(&other.body) {
             Ok(false) => {
                 return Ok(TestResult::fail(
@@ -86,19 +119,52 @@ impl SuiteVariables for ResponseDefinition {
     fn populate_variables(&mut self, variables: &mut VariableMap) -> Result<(), CaptiError> {
         self.headers.populate_variables(variables)?;
         self.body.populate_variables(variables)?;
+        self.cookies.populate_variables(variables)?;
+
+        if let Some(redirects) = &mut self.redirects {
+            redirects.populate_variables(variables)?;
+        }
 
         Ok(())
     }
 }
 
+impl VariableReferences for ResponseDefinition {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        let mut references = self
+            .headers
+            .variable_references(&format!("{}.headers", field));
+        references.extend(self.body.variable_references(&format!("{}.body", field)));
+
+        references.extend(
+            self.cookies
+                .variable_references(&format!("{}.cookies", field)),
+        );
+        references.extend(
+            self.redirects
+                .variable_references(&format!("{}.redirects", field)),
+        );
+
+        references
+    }
+}
+
 impl fmt::Display for ResponseDefinition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, " ")?;
 
+        if let Some(redirects) = self.redirects.as_ref().filter(|r| !r.is_empty()) {
+            writeln!(f, "Redirects:\n{}\n ", redirects.to_string().indent())?;
+        }
+
         writeln!(f, "Status: {}\n ", self.status)?;
 
         writeln!(f, "Headers:\n{}\n ", self.headers.to_string().indent())?;
 
+        if let Some(cookies) = self.cookies.as_ref().filter(|c| !c.is_empty()) {
+            writeln!(f, "Cookies:\n{}\n ", cookies.to_string().indent())?;
+        }
+
         if let Ok(json) = serde_json::to_string_pretty(&self.body) {
             writeln!(f, "Body:\n{}", json.indent())?;
         }
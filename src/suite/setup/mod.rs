@@ -1,5 +1,7 @@
+pub mod processes;
+mod script;
 pub mod suite_setup;
-mod wait_instruction;
+pub(crate) mod wait_instruction;
 
+pub use processes::Processes;
 pub use suite_setup::SuiteSetup;
-
@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    process::Child,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use once_cell::sync::Lazy;
+
+use crate::progress_println;
+
+use super::script::Script;
+
+/// How long a background process has to exit after it is asked to, before it is killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+type Tracked = (String, Arc<Mutex<Child>>);
+
+type Cleanup = Box<dyn FnOnce() + Send>;
+
+/// Every setup script that has been started, by process id, so they can all be stopped if
+/// the run is interrupted.
+static RUNNING: Lazy<Mutex<HashMap<u32, Tracked>>> = Lazy::new(Default::default);
+
+/// The background processes started by a suite's or the run's setup scripts, which are stopped
+/// when the suite or run finishes.
+#[derive(Debug, Default)]
+pub struct Processes {
+    pids: Mutex<Vec<u32>>,
+}
+
+impl Processes {
+    /// Stops the script when this suite or run finishes.
+    pub fn track(&self, script: Script) {
+        lock(&self.pids).push(script.pid());
+    }
+
+    /// Stops every tracked process and anything it started.
+    pub async fn terminate(&self) {
+        let pids = std::mem::take(&mut *lock(&self.pids));
+        if pids.is_empty() {
+            return;
+        }
+
+        let _ = tokio::task::spawn_blocking(move || terminate(&pids)).await;
+    }
+}
+
+/// Records a script as soon as it starts.
+pub(super) fn register(command: &str, child: Arc<Mutex<Child>>) {
+    let pid = lock(&child).id();
+    lock(&RUNNING).insert(pid, (command.to_string(), child));
+}
+
+/// Forgets a script that has exited on its own.
+pub(super) fn unregister(pid: u32) {
+    lock(&RUNNING).remove(&pid);
+}
+
+/// Stops a single script and anything it started.
+pub fn stop(script: &Script) {
+    terminate(&[script.pid()]);
+}
+
+/// Stops every setup script that is still running.
+pub fn terminate_all() {
+    let pids = lock(&RUNNING).keys().copied().collect::<Vec<u32>>();
+    terminate(&pids);
+}
+
+/// Run after the setup scripts are stopped when the run is interrupted, for anything else the run
+/// started outside of a script, by name.
+static CLEANUP: Lazy<Mutex<HashMap<&'static str, Cleanup>>> = Lazy::new(Default::default);
+
+/// Runs the cleanup if the run is interrupted with Ctrl-C. It replaces any cleanup already
+/// registered under the same name, so starting the same thing again only cleans it up once.
+pub fn on_interrupt(name: &'static str, cleanup: impl FnOnce() + Send + 'static) {
+    lock(&CLEANUP).insert(name, Box::new(cleanup));
+}
+
+/// Forgets the named cleanup, once what it cleans up has been stopped.
+pub fn forget_on_interrupt(name: &'static str) {
+    lock(&CLEANUP).remove(name);
+}
+
+/// Set once the run has been interrupted with Ctrl-C.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Stops every setup script and exits when the run is interrupted with Ctrl-C.
+pub fn terminate_on_interrupt() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            progress_println!("\nInterrupted, stopping setup processes...");
+            let _ = tokio::task::spawn_blocking(|| {
+                terminate_all();
+                for cleanup in std::mem::take(&mut *lock(&CLEANUP)).into_values() {
+                    cleanup();
+                }
+            })
+            .await;
+            std::process::exit(130);
+        }
+    });
+}
+
+fn terminate(pids: &[u32]) {
+    let mut running = lock(&RUNNING);
+    let children = pids
+        .iter()
+        .filter_map(|pid| running.remove(pid).map(|child| (*pid, child)))
+        .collect::<Vec<(u32, Tracked)>>();
+    drop(running);
+
+    terminate_children(children);
+}
+
+/// Asks each process group to exit, then kills any that are still running after the grace period.
+fn terminate_children(mut children: Vec<(u32, Tracked)>) {
+    children.retain(|(_, (command, child))| match lock(child).try_wait() {
+        Ok(Some(status)) => {
+            if !status.success() && !command.is_empty() {
+                progress_println!(
+                    "{}: Background setup script '{}' exited early with {}.",
+                    "WARN".yellow(),
+                    command,
+                    status
+                );
+            }
+            false
+        }
+        _ => true,
+    });
+
+    for (pid, (_, child)) in children.iter() {
+        request_stop(*pid, &mut lock(child));
+    }
+
+    let deadline = Instant::now() + GRACE_PERIOD;
+    while !children.is_empty() && Instant::now() < deadline {
+        children.retain(|(_, (_, child))| matches!(lock(child).try_wait(), Ok(None)));
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    for (pid, (_, child)) in children.iter() {
+        let mut child = lock(child);
+        force_stop(*pid, &mut child);
+        let _ = child.wait();
+    }
+}
+
+#[cfg(unix)]
+fn request_stop(pid: u32, _child: &mut Child) {
+    // Scripts lead their own process group, so this reaches anything they started
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGTERM);
+    }
+}
+
+#[cfg(not(unix))]
+fn request_stop(_pid: u32, child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(unix)]
+fn force_stop(pid: u32, child: &mut Child) {
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+#[cfg(not(unix))]
+fn force_stop(_pid: u32, child: &mut Child) {
+    let _ = child.kill();
+}
+
+pub(super) fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{errors::CaptiError, formatting::indent::Indent};
+
+use super::processes::{self, lock};
+
+/// Lines kept from each output stream, for reporting a failed script.
+const MAX_CAPTURED_LINES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// The most recent output of a setup script, or all of its stdout if it is being captured.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptOutput {
+    stdout: VecDeque<String>,
+    stderr: VecDeque<String>,
+    capture_stdout: bool,
+}
+
+impl ScriptOutput {
+    fn push(&mut self, stream: Stream, line: String) {
+        let (lines, limited) = match stream {
+            Stream::Stdout => (&mut self.stdout, !self.capture_stdout),
+            Stream::Stderr => (&mut self.stderr, true),
+        };
+
+        if limited && lines.len() == MAX_CAPTURED_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn stdout(&self) -> String {
+        self.stdout
+            .iter()
+            .cloned()
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn stderr(&self) -> String {
+        self.stderr
+            .iter()
+            .cloned()
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for ScriptOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.stdout.is_empty() && self.stderr.is_empty() {
+            return write!(f, "(no output)");
+        }
+
+        if !self.stdout.is_empty() {
+            writeln!(f, "stdout:\n{}", self.stdout().indent())?;
+        }
+
+        if !self.stderr.is_empty() {
+            writeln!(f, "stderr:\n{}", self.stderr().indent())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A running setup script. Its output is captured as it is written, and appended to its log
+/// file if it has one.
+pub struct Script {
+    pub command: String,
+    child: Arc<Mutex<Child>>,
+    output: Arc<Mutex<ScriptOutput>>,
+    lines: Receiver<(Stream, String)>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl Script {
+    /// Starts the script in its own process group, so anything it starts can be stopped along
+    /// with it.
+    pub fn spawn(command: &str, log_file: Option<&Path>) -> Result<Script, CaptiError> {
+        Script::start(command, log_file, ScriptOutput::default())
+    }
+
+    /// Starts the script like `spawn`, but keeps everything it writes to stdout rather than only
+    /// the most recent lines.
+    pub fn spawn_capturing(command: &str, log_file: Option<&Path>) -> Result<Script, CaptiError> {
+        let output = ScriptOutput {
+            capture_stdout: true,
+            ..ScriptOutput::default()
+        };
+        Script::start(command, log_file, output)
+    }
+
+    fn start(
+        command: &str,
+        log_file: Option<&Path>,
+        output: ScriptOutput,
+    ) -> Result<Script, CaptiError> {
+        let mut cmd = shell_command(command);
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+        let log = match log_file {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| {
+                        CaptiError::setup_error(format!(
+                            "Unable to open log file {:?} for script '{}': {}",
+                            path, command, e
+                        ))
+                    })?;
+                Some(Arc::new(Mutex::new(file)))
+            }
+            None => None,
+        };
+
+        let mut child = cmd.spawn().map_err(|e| {
+            CaptiError::setup_error(format!("Unable to start script '{}': {}", command, e))
+        })?;
+
+        let output = Arc::new(Mutex::new(output));
+        let (sender, lines) = mpsc::channel();
+        let mut readers = Vec::new();
+
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(read_lines(
+                stdout,
+                Stream::Stdout,
+                output.clone(),
+                log.clone(),
+                sender.clone(),
+            ));
+        }
+
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(read_lines(
+                stderr,
+                Stream::Stderr,
+                output.clone(),
+                log,
+                sender,
+            ));
+        }
+
+        let child = Arc::new(Mutex::new(child));
+        processes::register(command, child.clone());
+
+        Ok(Script {
+            command: command.to_string(),
+            child,
+            output,
+            lines,
+            readers,
+        })
+    }
+
+    pub fn output(&self) -> ScriptOutput {
+        match self.output.lock() {
+            Ok(output) => output.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Waits until the deadline, if there is one, for the next line the script writes. Fails
+    /// with `Disconnected` once its output is closed.
+    pub fn next_line(
+        &self,
+        deadline: Option<Instant>,
+    ) -> Result<(Stream, String), RecvTimeoutError> {
+        match deadline {
+            Some(deadline) => self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .lines
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        lock(&self.child).id()
+    }
+
+    /// Returns the exit status if the script has exited.
+    pub fn try_status(&mut self) -> Option<ExitStatus> {
+        lock(&self.child).try_wait().ok().flatten()
+    }
+
+    /// Returns an error with the script's output if it has already exited unsuccessfully.
+    pub fn check_running(&mut self) -> Result<(), CaptiError> {
+        match self.try_status() {
+            Some(status) if !status.success() => Err(self.failure(status)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits for the script to exit and for all of its output to be read, returning an error
+    /// with the output if it exits unsuccessfully. A script still running after the timeout is
+    /// stopped.
+    pub fn wait(mut self, timeout: Option<Duration>) -> Result<ScriptOutput, CaptiError> {
+        let started = Instant::now();
+
+        // Polled so the process can still be stopped if the run is interrupted meanwhile
+        let status = loop {
+            let status = lock(&self.child).try_wait().map_err(|e| {
+                CaptiError::setup_error(format!(
+                    "Unable to wait for script '{}': {}",
+                    self.command, e
+                ))
+            })?;
+
+            match (status, timeout) {
+                (Some(status), _) => break status,
+                (None, Some(timeout)) if started.elapsed() >= timeout => {
+                    processes::stop(&self);
+                    return Err(self.timed_out("it to finish", timeout));
+                }
+                (None, _) => thread::sleep(Duration::from_millis(20)),
+            }
+        };
+
+        processes::unregister(self.pid());
+
+        for reader in self.readers.drain(..) {
+            let _ = reader.join();
+        }
+
+        match status.success() {
+            true => Ok(self.output()),
+            false => Err(self.failure(status)),
+        }
+    }
+
+    /// Describes a condition the script didn't meet in time, with the output it wrote.
+    pub fn timed_out(&self, waiting_for: &str, timeout: Duration) -> CaptiError {
+        CaptiError::setup_error(format!(
+            "Timed out after {} seconds waiting for {} from script '{}'.\n{}",
+            timeout.as_secs_f64(),
+            waiting_for,
+            self.command,
+            self.output().to_string().indent()
+        ))
+    }
+
+    /// Describes a script that exited unsuccessfully, with the output it wrote.
+    pub fn failure(&self, status: ExitStatus) -> CaptiError {
+        let status = match status.code() {
+            Some(code) => format!("exit code {}", code),
+            None => String::from("a signal"),
+        };
+
+        CaptiError::setup_error(format!(
+            "Script '{}' exited with {}.\n{}",
+            self.command,
+            status,
+            self.output().to_string().indent()
+        ))
+    }
+}
+
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new(match cfg!(target_os = "windows") {
+        true => "cmd",
+        false => "sh",
+    });
+
+    match cfg!(target_os = "windows") {
+        true => cmd.args(["/C", command]),
+        false => cmd.arg("-c").arg(command),
+    };
+
+    cmd
+}
+
+fn read_lines(
+    source: impl Read + Send + 'static,
+    stream: Stream,
+    output: Arc<Mutex<ScriptOutput>>,
+    log: Option<Arc<Mutex<File>>>,
+    sender: Sender<(Stream, String)>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let reader = BufReader::new(source);
+
+        for line in reader.split(b'\n') {
+            let Ok(line) = line else {
+                break;
+            };
+
+            let line = String::from_utf8_lossy(&line)
+                .trim_end_matches('\r')
+                .to_string();
+
+            if let Some(Ok(mut file)) = log.as_ref().map(|log| log.lock()) {
+                let _ = writeln!(file, "{}", line);
+            }
+
+            if let Ok(mut output) = output.lock() {
+                output.push(stream, line.clone());
+            }
+
+            // Nobody is listening once a background script has been handed off
+            let _ = sender.send((stream, line));
+        }
+    })
+}
@@ -1,15 +1,35 @@
 use std::{
-    io::{BufRead, BufReader},
-    net::{SocketAddr, TcpStream},
-    process::{Command, Stdio},
-    time::Duration,
+    collections::HashSet,
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::mpsc::RecvTimeoutError,
+    time::{Duration, Instant},
 };
 
 use serde::Deserialize;
 
-use crate::{progress::Spinner, progress_println};
+use crate::{
+    client::Client,
+    errors::CaptiError,
+    formatting::indent::Indent,
+    m_value::{m_match::MMatch, m_value::MValue, status_matcher::StatusMatcher},
+    progress::Spinner,
+    progress_println,
+    suite::{
+        extract::ResponseExtractor, request::RequestDefinition, response::status::Status,
+        response::ResponseDefinition, test_result::TestResult,
+    },
+    variables::{variable_map::VariableMap, SuiteVariables},
+};
+
+use super::{
+    processes::{self, Processes},
+    script::{Script, ScriptOutput},
+    wait_instruction::{HttpCheck, WaitInstruction},
+};
 
-use super::wait_instruction::WaitInstruction;
+/// The longest a single health check request may take.
+const HTTP_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct SuiteSetup {
@@ -17,58 +37,165 @@ pub struct SuiteSetup {
     before_each: Option<Vec<SetupInstruction>>,
     after_all: Option<Vec<SetupInstruction>>,
     after_each: Option<Vec<SetupInstruction>>,
+    /// Runs after the tests if any of them failed, or if setup failed.
+    on_failure: Option<Vec<SetupInstruction>>,
+    /// Runs last, even if setup failed.
+    always: Option<Vec<SetupInstruction>>,
 }
 
+/// A single step of a setup hook, which either runs a `script` or sends a `request`.
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct SetupInstruction {
     description: Option<String>,
-    script: String,
+    script: Option<String>,
+    request: Option<RequestDefinition>,
+    /// The response expected for a request. Any 2xx status is expected by default.
+    expect: Option<ResponseDefinition>,
+    extract: Option<ResponseExtractor>,
     wait_until: Option<WaitInstruction>,
+    /// Everything the script writes to stdout and stderr is appended to this file.
+    log_file: Option<PathBuf>,
+    /// Leaves the script running in the background once its output is detected.
+    #[serde(default)]
+    keep_running: bool,
+    /// Stores the trimmed stdout of a finished script in this variable.
+    capture_as: Option<String>,
+    /// Parses the captured stdout as JSON rather than keeping it as a string.
+    #[serde(default)]
+    capture_json: bool,
 }
 
-impl SuiteSetup {
-    pub async fn execute_before_all(&self) {
-        if let Some(before_all) = &self.before_all {
-            SuiteSetup::execute_instructions(before_all)
-                .await
-                .expect("Failed to execute 'before_all' setup instructions.");
-        }
+impl SetupInstruction {
+    /// Returns the names of the variables the step extracts from its response or captures from
+    /// its script.
+    pub fn extracted_variables(&self) -> HashSet<String> {
+        let mut names = match &self.extract {
+            Some(extractor) => extractor.variable_names(),
+            None => HashSet::new(),
+        };
+        names.extend(self.capture_as.clone());
+        names
     }
+}
 
-    pub async fn execute_before_each(&self) {
-        if let Some(before_each) = &self.before_each {
-            SuiteSetup::execute_instructions(before_each)
-                .await
-                .expect("Failed to execute 'before_each' setup instructions.");
+/// What setup hooks run with: the processes they start, and the client and variables that their
+/// requests use.
+pub struct SetupContext<'a> {
+    pub processes: &'a Processes,
+    pub client: &'a Client,
+    pub variables: &'a mut VariableMap,
+}
+
+impl<'a> SetupContext<'a> {
+    pub fn new(
+        processes: &'a Processes,
+        client: &'a Client,
+        variables: &'a mut VariableMap,
+    ) -> Self {
+        SetupContext {
+            processes,
+            client,
+            variables,
         }
     }
+}
 
-    pub async fn execute_after_all(&self) {
-        if let Some(after_all) = &self.after_all {
-            SuiteSetup::execute_instructions(after_all)
-                .await
-                .expect("Failed to execute 'after_all' setup instructions.");
-        }
+impl SuiteSetup {
+    pub async fn execute_before_all(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("before_all", &self.before_all, context).await
     }
 
-    pub async fn execute_after_each(&self) {
-        if let Some(after_each) = &self.after_each {
-            SuiteSetup::execute_instructions(after_each)
+    pub async fn execute_before_each(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("before_each", &self.before_each, context).await
+    }
+
+    pub async fn execute_after_all(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("after_all", &self.after_all, context).await
+    }
+
+    pub async fn execute_after_each(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("after_each", &self.after_each, context).await
+    }
+
+    pub async fn execute_on_failure(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("on_failure", &self.on_failure, context).await
+    }
+
+    pub async fn execute_always(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("always", &self.always, context).await
+    }
+
+    /// Returns the names of the variables extracted by requests or captured from scripts in the
+    /// `before_all` hook, which are available to every test.
+    pub fn extracted_variables(&self) -> HashSet<String> {
+        self.before_all
+            .iter()
+            .flatten()
+            .flat_map(|instruction| instruction.extracted_variables())
+            .collect()
+    }
+
+    /// Runs each step of a hook in order, stopping at the first that fails.
+    pub async fn execute_hook(
+        hook: &str,
+        instructions: &Option<Vec<SetupInstruction>>,
+        context: SetupContext<'_>,
+    ) -> Result<(), CaptiError> {
+        match instructions {
+            Some(instructions) => SuiteSetup::execute_instructions(instructions, context)
                 .await
-                .expect("Failed to execute 'after_each' setup instructions.");
+                .map_err(|e| match e {
+                    CaptiError::SetupError(message) => {
+                        CaptiError::setup_error(format!("[{}] {}", hook, message))
+                    }
+                    e => e,
+                }),
+            None => Ok(()),
         }
     }
 
-    async fn execute_instructions(instructions: &Vec<SetupInstruction>) -> Result<(), ()> {
+    async fn execute_instructions(
+        instructions: &Vec<SetupInstruction>,
+        context: SetupContext<'_>,
+    ) -> Result<(), CaptiError> {
         for setup_instruction in instructions {
-            let progress_str = match setup_instruction.description.as_ref() {
-                Some(description) => description,
-                None => "Setup script",
+            let progress_str = match (&setup_instruction.description, &setup_instruction.request) {
+                (Some(description), _) => description.clone(),
+                (None, Some(request)) => request.summary(),
+                (None, None) => String::from("Setup script"),
             };
 
             let spinner = Spinner::start(progress_str).await;
 
-            SuiteSetup::execute_single_instruction(setup_instruction).await?;
+            let result = match (&setup_instruction.script, &setup_instruction.request) {
+                (Some(script), None) => {
+                    SuiteSetup::execute_single_instruction(
+                        script,
+                        setup_instruction,
+                        context.processes,
+                        context.client,
+                        context.variables,
+                    )
+                    .await
+                }
+                (None, Some(request)) => {
+                    SuiteSetup::execute_request(
+                        request,
+                        setup_instruction,
+                        context.client,
+                        context.variables,
+                    )
+                    .await
+                }
+                _ => Err(CaptiError::setup_error(
+                    "Each setup step must have either a 'script' or a 'request'.",
+                )),
+            };
+
+            if let Err(e) = result {
+                spinner.finish("Failed.");
+                return Err(e);
+            }
 
             spinner.finish("Done.");
             progress_println!(" ");
@@ -77,173 +204,369 @@ impl SuiteSetup {
         Ok(())
     }
 
-    async fn execute_single_instruction(instruction: &SetupInstruction) -> Result<(), ()> {
-        let mut cmd = Command::new(match cfg!(target_os = "windows") {
-            true => "cmd",
-            false => "sh",
-        });
-
-        match cfg!(target_os = "windows") {
-            true => cmd.args(["/C", &instruction.script]),
-            false => cmd.arg("-c").arg(&instruction.script),
+    /// Sends a request step, checking the response and extracting any variables from it.
+    async fn execute_request(
+        request: &RequestDefinition,
+        instruction: &SetupInstruction,
+        client: &Client,
+        variables: &mut VariableMap,
+    ) -> Result<(), CaptiError> {
+        let mut request = request.clone();
+        request.populate_variables(variables)?;
+
+        let failed = |message: String| {
+            CaptiError::setup_error(format!("Request {} failed: {}", request.summary(), message))
         };
 
-        let script = instruction.script.clone();
+        let response = request
+            .send(client)
+            .await
+            .map_err(|e| failed(e.to_string()))?;
 
-        match &instruction.wait_until {
-            Some(WaitInstruction::Finished) => {
-                tokio::task::spawn_blocking(move || {
-                    cmd.output().map_err(|e| {
-                        eprintln!("Failed to spawn script instruction: {}\n{:#?}", script, e);
-                    })?;
+        let result = match &instruction.expect {
+            Some(expect) => {
+                let mut expect = expect.clone();
+                expect.populate_variables(variables)?;
+                expect.compare(&response)?
+            }
+            None => {
+                let expected = Status::from("2xx");
+                match expected.matches(&response.status)? {
+                    true => TestResult::Passed,
+                    false => TestResult::fail(
+                        "Status does not match.",
+                        expected.get_context(&response.status),
+                    ),
+                }
+            }
+        };
 
-                    Ok(()) as Result<(), ()>
-                })
+        if let TestResult::Failed(report) = result {
+            return Err(CaptiError::setup_error(format!(
+                "Request {} failed.\n{}",
+                request.summary(),
+                report.to_string().trim_end().indent()
+            )));
+        }
+
+        if let Some(extractor) = &instruction.extract {
+            extractor
+                .extract(&response, variables)
                 .await
-                .map_err(|e| {
-                    eprintln!(
-                        "Failed to spawn script instruction: {}\n{:#?}",
-                        &instruction.script, e
-                    );
-                })??;
-            }
-            Some(WaitInstruction::Stdout(output)) => {
-                let mut process = cmd
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()
-                    .map_err(|e| {
-                        eprintln!(
-                            "Failed to spawn script instruction: {}\n{:#?}",
-                            &instruction.script, e
-                        );
-                    })?;
-
-                let spinner =
-                    Spinner::start(format!("Detecting output '{}'", output.as_str())).await;
-                let output = output.clone();
-
-                tokio::task::spawn_blocking(move || {
-                    let stdout = process
-                        .stdout
-                        .take()
-                        .ok_or(String::from("Stdout unavailable"))
-                        .map_err(|e| {
-                            eprintln!(
-                                "Failed to read stdout for script instruction: {}\n{:#?}",
-                                script, e
-                            );
-                        })?;
-
-                    // process.stderr.take();
-
-                    let mut reader = BufReader::new(stdout);
-                    let mut line = String::new();
-
-                    loop {
-                        let bytes_read = reader.read_line(&mut line).map_err(|e| {
-                            eprintln!(
-                                "Failed to read stdout for script instruction: {}\n{:#?}",
-                                script, e
-                            );
-                        })?;
-
-                        if bytes_read == 0 {
-                            break;
-                        }
+                .map_err(|e| failed(e.to_string()))?;
+        }
 
-                        if line.contains(output.as_str()) {
-                            break;
-                        }
+        Ok(())
+    }
 
-                        line.clear();
-                    }
+    async fn execute_single_instruction(
+        command: &str,
+        instruction: &SetupInstruction,
+        processes: &Processes,
+        client: &Client,
+        variables: &mut VariableMap,
+    ) -> Result<(), CaptiError> {
+        let log_file = instruction.log_file.as_deref();
+
+        match (&instruction.capture_as, &instruction.wait_until) {
+            (Some(_), Some(WaitInstruction::Finished(_))) | (None, _) => {}
+            (Some(name), _) => {
+                return Err(CaptiError::setup_error(format!(
+                    "Script '{}' must use 'wait_until: finished' to capture its output as {}.",
+                    command, name
+                )));
+            }
+        }
 
-                    if let Err(e) = process.kill() {
-                        eprintln!("Failed to kill process: {}\n{:#?}", script, e);
+        match &instruction.wait_until {
+            Some(WaitInstruction::Finished(timeout)) => {
+                let script = match instruction.capture_as {
+                    Some(_) => Script::spawn_capturing(command, log_file)?,
+                    None => Script::spawn(command, log_file)?,
+                };
+                let timeout = *timeout;
+                let output = tokio::task::spawn_blocking(move || script.wait(timeout))
+                    .await
+                    .map_err(|e| join_error(command, e))??;
+
+                if let Some(name) = &instruction.capture_as {
+                    let value = captured_value(command, &output, instruction.capture_json)?;
+                    variables.insert(name.clone(), value);
+                }
+            }
+            Some(WaitInstruction::Output {
+                stream,
+                pattern,
+                timeout,
+            }) => {
+                let script = Script::spawn(command, log_file)?;
+
+                let waiting_for = format!("{} {}", stream, pattern);
+                let spinner = Spinner::start(format!("Detecting {}", &waiting_for)).await;
+                let (stream, pattern, timeout) = (*stream, pattern.clone(), *timeout);
+                let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+                let (script, detected) = tokio::task::spawn_blocking(move || loop {
+                    match script.next_line(deadline) {
+                        Ok((line_stream, line))
+                            if stream.includes(line_stream) && pattern.is_match(&line) =>
+                        {
+                            return (script, Ok(true));
+                        }
+                        Ok(_) => continue,
+                        Err(RecvTimeoutError::Disconnected) => return (script, Ok(false)),
+                        Err(RecvTimeoutError::Timeout) => {
+                            let error = script.timed_out(&waiting_for, timeout.unwrap_or_default());
+                            return (script, Err(error));
+                        }
                     }
-
-                    Ok(()) as Result<(), ()>
                 })
                 .await
-                .map_err(|e| {
-                    eprintln!(
-                        "Failed to read stdout for script instruction: {}\n{:#?}",
-                        &instruction.script, e
-                    );
-                })??;
+                .map_err(|e| join_error(command, e))?;
+
+                match detected {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        spinner.finish("Not detected.");
+                        // An unsuccessful exit explains more than the missing output
+                        let remaining = deadline
+                            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                        let output = tokio::task::spawn_blocking(move || script.wait(remaining))
+                            .await
+                            .map_err(|e| join_error(command, e))??;
+                        return Err(CaptiError::setup_error(format!(
+                            "Script '{}' finished without writing the expected output.\n{}",
+                            command,
+                            output.to_string().indent()
+                        )));
+                    }
+                    Err(e) => {
+                        spinner.finish("Timed out.");
+                        stop(script).await;
+                        return Err(e);
+                    }
+                }
+
+                match instruction.keep_running {
+                    true => processes.track(script),
+                    false => stop(script).await,
+                }
 
                 spinner.finish("Detected.");
             }
             Some(WaitInstruction::Seconds(seconds)) => {
-                cmd.spawn().map_err(|e| {
-                    eprintln!(
-                        "Failed to spawn script instruction: {}\n{:#?}",
-                        &instruction.script, e
-                    );
-                })?;
+                let mut script = Script::spawn(command, log_file)?;
 
                 tokio::time::sleep(Duration::from_secs_f64(*seconds)).await;
+
+                let result = script.check_running();
+                processes.track(script);
+                result?;
             }
-            Some(WaitInstruction::Port(port)) => {
-                let port = port.clone();
+            Some(WaitInstruction::Port {
+                host,
+                port,
+                timeout,
+            }) => {
+                let (host, port, timeout) = (host.clone(), *port, *timeout);
+                let target = format!("{}:{}", host, port);
+
+                if port_open(&host, port) {
+                    // In case app is already running on this port
+                    return Ok(());
+                }
 
-                let spinner = Spinner::start(format!("Waiting for open port: {}", &port)).await;
+                let spinner = Spinner::start(format!("Waiting for open port: {}", &target)).await;
 
-                tokio::task::spawn_blocking(move || {
-                    let addr: SocketAddr = format!("127.0.0.1:{}", &port).parse().map_err(|e| {
-                        eprintln!(
-                            "Failed to parse socket from port address: {}\n{:#?}",
-                            script, e
-                        );
-                    })?;
+                let mut script = Script::spawn(command, log_file)?;
+                let started = Instant::now();
 
-                    if let Ok(_) = TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
-                        // In case app is already running on this port
-                        return Ok(());
+                let (script, result) = tokio::task::spawn_blocking(move || loop {
+                    if let Err(e) = script.check_running() {
+                        return (script, Err(e));
                     }
 
-                    cmd.spawn().map_err(|e| {
-                        eprintln!("Failed to spawn script instruction: {}\n{:#?}", script, e);
-                    })?;
+                    if port_open(&host, port) {
+                        return (script, Ok(()));
+                    }
 
-                    loop {
-                        if let Ok(_) = TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
-                            break;
-                        }
+                    if let Some(timeout) = timeout.filter(|timeout| started.elapsed() >= *timeout) {
+                        let error = script.timed_out(&format!("port {}", target), timeout);
+                        return (script, Err(error));
                     }
 
-                    Ok(()) as Result<(), ()>
+                    std::thread::sleep(Duration::from_millis(100));
                 })
                 .await
-                .map_err(|e| {
-                    eprintln!(
-                        "Failed to read stdout for script instruction: {}\n{:#?}",
-                        &instruction.script, e
-                    );
-                })??;
-
-                spinner.finish("Opened.")
+                .map_err(|e| join_error(command, e))?;
+
+                match result {
+                    Ok(()) => {
+                        processes.track(script);
+                        spinner.finish("Opened.");
+                    }
+                    Err(e) => {
+                        spinner.finish("Failed.");
+                        stop(script).await;
+                        return Err(e);
+                    }
+                }
+            }
+            Some(WaitInstruction::Http(check)) => {
+                // Checks are sent with the suite's client, so they can reach services that use a
+                // local CA or a proxy
+                if http_check(client, check).await.is_ok() {
+                    // In case app is already running and ready
+                    return Ok(());
+                }
+
+                let spinner = Spinner::start(format!(
+                    "Waiting for {} {}",
+                    check.method.as_str(),
+                    &check.url
+                ))
+                .await;
+
+                let mut script = Script::spawn(command, log_file)?;
+                let started = Instant::now();
+
+                let result = loop {
+                    if let Err(e) = script.check_running() {
+                        break Err(e);
+                    }
+
+                    let last_result = match http_check(client, check).await {
+                        Ok(()) => break Ok(()),
+                        Err(result) => result,
+                    };
+
+                    if let Some(timeout) = check
+                        .timeout
+                        .filter(|timeout| started.elapsed() >= *timeout)
+                    {
+                        let waiting_for = format!(
+                            "{} {} (last result: {})",
+                            check.method.as_str(),
+                            &check.url,
+                            last_result
+                        );
+                        break Err(script.timed_out(&waiting_for, timeout));
+                    }
+
+                    tokio::time::sleep(Duration::from_millis(250)).await;
+                };
+
+                match result {
+                    Ok(()) => {
+                        processes.track(script);
+                        spinner.finish("Ready.");
+                    }
+                    Err(e) => {
+                        spinner.finish("Failed.");
+                        stop(script).await;
+                        return Err(e);
+                    }
+                }
             }
             None => {
-                cmd.spawn().map_err(|e| {
-                    eprintln!(
-                        "Failed to spawn script instruction: {}\n{:#?}",
-                        &instruction.script, e
-                    );
-                })?;
+                let script = Script::spawn(command, log_file)?;
+                processes.track(script);
             }
         }
+
         Ok(())
     }
 }
 
+/// Sends the check's request once, returning a description of the response if it doesn't
+/// match.
+async fn http_check(client: &reqwest::Client, check: &HttpCheck) -> Result<(), String> {
+    let method =
+        reqwest::Method::from_bytes(check.method.as_str().as_bytes()).map_err(|e| e.to_string())?;
+
+    let response = client
+        .request(method, &check.url)
+        .timeout(HTTP_CHECK_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let status = StatusMatcher::from(response.status());
+    if !check.status.matches(&status).map_err(|e| e.to_string())? {
+        return Err(format!("status {}", status));
+    }
+
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let body = match serde_json::from_str::<MValue>(&text) {
+        Ok(body) => body,
+        Err(_) => MValue::String(text),
+    };
+
+    match check.body.matches(&body) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("status {} with body {}", status, body)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Returns a finished script's trimmed stdout, parsed as JSON if requested.
+fn captured_value(command: &str, output: &ScriptOutput, json: bool) -> Result<MValue, CaptiError> {
+    let stdout = output.stdout();
+    let stdout = stdout.trim();
+
+    match json {
+        true => serde_json::from_str::<MValue>(stdout).map_err(|e| {
+            CaptiError::setup_error(format!(
+                "Unable to parse the output of script '{}' as JSON: {}\n{}",
+                command,
+                e,
+                output.to_string().indent()
+            ))
+        }),
+        false => Ok(MValue::String(stdout.to_string())),
+    }
+}
+
+/// Stops a script that is no longer needed, along with anything it started.
+async fn stop(script: Script) {
+    let _ = tokio::task::spawn_blocking(move || processes::stop(&script)).await;
+}
+
+/// Returns true if a connection can be made to the port. The host is resolved on every check,
+/// since it may not exist until the script starts it.
+fn port_open(host: &str, port: u16) -> bool {
+    let Ok(addrs) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, Duration::from_secs(1)).is_ok())
+}
+
+fn join_error(script: &str, e: tokio::task::JoinError) -> CaptiError {
+    CaptiError::setup_error(format!("Script '{}' could not be run: {}", script, e))
+}
+
 #[cfg(test)]
 mod test {
+    use once_cell::sync::Lazy;
     use serde_json::json;
 
     use super::*;
 
+    /// Built once, so building it isn't counted in the timing of tests.
+    static CLIENT: Lazy<Client> = Lazy::new(Client::default);
+
+    async fn before_all(setup: &SuiteSetup, processes: &Processes) -> Result<(), CaptiError> {
+        let context = SetupContext {
+            processes,
+            client: &CLIENT,
+            variables: &mut VariableMap::new(),
+        };
+        setup.execute_before_all(context).await
+    }
+
     #[test]
     fn deserializes_wait_instruction() {
         let setup = json!({
@@ -270,7 +593,7 @@ mod test {
         );
         assert_eq!(
             setup.before_all.as_ref().unwrap()[2].wait_until,
-            Some(WaitInstruction::Finished)
+            Some(WaitInstruction::Finished(None))
         );
     }
 
@@ -286,7 +609,7 @@ mod test {
         });
         let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
 
-        setup.execute_before_all().await;
+        before_all(&setup, &Processes::default()).await.unwrap();
     }
 
     #[tokio::test]
@@ -306,8 +629,9 @@ mod test {
         });
         let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
 
+        Lazy::force(&CLIENT);
         let now = std::time::Instant::now();
-        setup.execute_before_all().await;
+        before_all(&setup, &Processes::default()).await.unwrap();
         assert!(now.elapsed().as_millis() < 1100);
     }
 
@@ -327,10 +651,265 @@ mod test {
         });
         let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
 
+        Lazy::force(&CLIENT);
         let now = std::time::Instant::now();
-        setup.execute_before_all().await;
+        before_all(&setup, &Processes::default()).await.unwrap();
         let elapsed = now.elapsed().as_millis();
         assert!(elapsed >= 1000);
         assert!(elapsed < 1100);
     }
+
+    #[tokio::test]
+    async fn fails_with_output_on_non_zero_exit() {
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "echo 'seeding'; echo 'connection refused' >&2; exit 3",
+                    "wait_until": "finished"
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+
+        let error = before_all(&setup, &Processes::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("[before_all]"));
+        assert!(error.contains("exit code 3"));
+        assert!(error.contains("seeding"));
+        assert!(error.contains("connection refused"));
+    }
+
+    #[tokio::test]
+    async fn appends_output_to_log_file() {
+        let log_file = std::env::temp_dir().join(format!("capti-setup-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log_file);
+
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "echo 'first'",
+                    "wait_until": "finished",
+                    "log_file": &log_file,
+                },
+                {
+                    "script": "echo 'second' >&2",
+                    "wait_until": "finished",
+                    "log_file": &log_file,
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+        before_all(&setup, &Processes::default()).await.unwrap();
+
+        let log = std::fs::read_to_string(&log_file).unwrap();
+        let _ = std::fs::remove_file(&log_file);
+
+        assert_eq!(log, "first\nsecond\n");
+    }
+
+    #[tokio::test]
+    async fn terminates_background_processes() {
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "sleep 30",
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+
+        let processes = Processes::default();
+        before_all(&setup, &processes).await.unwrap();
+
+        let now = std::time::Instant::now();
+        processes.terminate().await;
+        assert!(now.elapsed().as_secs() < 2);
+    }
+
+    #[tokio::test]
+    async fn fails_with_output_when_wait_times_out() {
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "echo 'starting'; sleep 5",
+                    "wait_until": "output 'ready' within 0.5 seconds"
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+
+        let now = std::time::Instant::now();
+        let error = before_all(&setup, &Processes::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(now.elapsed().as_secs() < 2);
+        assert!(error.contains("Timed out after 0.5 seconds waiting for stdout 'ready'"));
+        assert!(error.contains("starting"));
+    }
+
+    #[tokio::test]
+    async fn fails_with_output_when_http_check_times_out() {
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "echo 'running migrations'; sleep 5",
+                    "wait_until": "http GET http://127.0.0.1:1/health 200 within 0.5 seconds"
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+
+        let now = std::time::Instant::now();
+        let error = before_all(&setup, &Processes::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(now.elapsed().as_secs() < 2);
+        assert!(error.contains("waiting for GET http://127.0.0.1:1/health"));
+        assert!(error.contains("running migrations"));
+    }
+
+    #[tokio::test]
+    async fn keeps_running_after_detecting_stderr_output() {
+        let marker = std::env::temp_dir().join(format!("capti-running-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": format!(
+                        "echo 'Listening on 3000' >&2; sleep 0.5; touch {}; sleep 30",
+                        marker.display()
+                    ),
+                    "wait_until": r"stderr /Listening on \d+/ within 5 seconds",
+                    "keep_running": true,
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+
+        let processes = Processes::default();
+        before_all(&setup, &processes).await.unwrap();
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let still_running = marker.exists();
+        processes.terminate().await;
+        let _ = std::fs::remove_file(&marker);
+
+        assert!(still_running);
+    }
+
+    #[tokio::test]
+    async fn sends_requests_and_extracts_variables() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            use std::io::{Read, Write};
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+
+            let body = r#"{"id": "abc123", "name": "fixture"}"#;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        });
+
+        let setup = json!({
+            "before_all": [
+                {
+                    "request": {
+                        "method": "POST",
+                        "url": "http://127.0.0.1:${PORT}/users",
+                        "body": { "name": "fixture" },
+                    },
+                    "expect": { "status": 201 },
+                    "extract": { "body": { "id": "${USER_ID}" } },
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+        assert!(setup.extracted_variables().contains("USER_ID"));
+
+        let mut variables = VariableMap::new();
+        variables.insert("PORT", port.to_string());
+        let context = SetupContext {
+            processes: &Processes::default(),
+            client: &CLIENT,
+            variables: &mut variables,
+        };
+        setup.execute_before_all(context).await.unwrap();
+
+        assert_eq!(
+            variables.get("USER_ID"),
+            Some(MValue::String(String::from("abc123")))
+        );
+    }
+
+    #[tokio::test]
+    async fn captures_script_output_as_variables() {
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "echo '  token-123  '",
+                    "wait_until": "finished",
+                    "capture_as": "TOKEN",
+                },
+                {
+                    "script": "echo '{\"id\": 42, \"tags\": [\"a\", \"b\"]}'",
+                    "wait_until": "finished",
+                    "capture_as": "FIXTURE",
+                    "capture_json": true,
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+        assert!(setup.extracted_variables().contains("FIXTURE"));
+
+        let mut variables = VariableMap::new();
+        let context = SetupContext {
+            processes: &Processes::default(),
+            client: &CLIENT,
+            variables: &mut variables,
+        };
+        setup.execute_before_all(context).await.unwrap();
+
+        assert_eq!(
+            variables.get("TOKEN"),
+            Some(MValue::String(String::from("token-123")))
+        );
+        assert_eq!(
+            variables.get("FIXTURE"),
+            Some(serde_json::from_str::<MValue>(r#"{"id": 42, "tags": ["a", "b"]}"#).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn only_captures_output_of_finished_scripts() {
+        let setup = json!({
+            "before_all": [
+                {
+                    "script": "echo 'token'",
+                    "capture_as": "TOKEN",
+                },
+            ]
+        });
+        let setup = serde_json::from_value::<SuiteSetup>(setup).unwrap();
+
+        let error = before_all(&setup, &Processes::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("must use 'wait_until: finished'"));
+    }
 }
@@ -1,16 +1,186 @@
-use std::fmt;
+use std::{fmt, time::Duration};
 
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::{
-    de::{self, Visitor},
+    de::{self, IntoDeserializer, MapAccess, Visitor},
     Deserialize, Deserializer,
 };
 
+use crate::{
+    m_value::{m_value::MValue, status_matcher::StatusMatcher},
+    suite::request::request_method::RequestMethod,
+};
+
+use super::script::Stream;
+
+/// The host checked by `port` instructions that only name a port.
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// Matches a trailing timeout like `within 60 seconds` or `within 2m`.
+static TIMEOUT_MATCHER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(.*?)\s+within\s+(\d+(?:\.\d+)?\s*(?:seconds?|s|minutes?|m)?)$")
+        .expect("Invalid timeout regex.")
+});
+
+/// Matches a duration like `60 seconds`, `2m`, or `30`.
+static DURATION_MATCHER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\d+(?:\.\d+)?)\s*(seconds?|s|minutes?|m)?$").expect("Invalid duration regex.")
+});
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum WaitInstruction {
-    Finished,
+    Finished(Option<Duration>),
     Seconds(f64),
-    Port(u64),
-    Stdout(String),
+    Port {
+        host: String,
+        port: u16,
+        timeout: Option<Duration>,
+    },
+    Output {
+        stream: OutputStream,
+        pattern: OutputPattern,
+        timeout: Option<Duration>,
+    },
+    Http(HttpCheck),
+}
+
+/// The output streams searched by an output wait instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+    Any,
+}
+
+impl OutputStream {
+    pub fn includes(&self, stream: Stream) -> bool {
+        match self {
+            OutputStream::Stdout => stream == Stream::Stdout,
+            OutputStream::Stderr => stream == Stream::Stderr,
+            OutputStream::Any => true,
+        }
+    }
+}
+
+impl fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputStream::Stdout => write!(f, "stdout"),
+            OutputStream::Stderr => write!(f, "stderr"),
+            OutputStream::Any => write!(f, "any output"),
+        }
+    }
+}
+
+/// The text an output wait instruction looks for in a line, either as a substring or as a
+/// regex written between slashes.
+#[derive(Debug, Clone)]
+pub enum OutputPattern {
+    Text(String),
+    Regex(Regex),
+}
+
+impl OutputPattern {
+    fn parse(value: &str) -> Result<OutputPattern, String> {
+        let value = value.trim();
+
+        if value.is_empty() {
+            return Err(String::from("Missing output to wait for."));
+        }
+
+        for quote in ['\'', '"'] {
+            if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+                return Ok(OutputPattern::Text(value[1..value.len() - 1].to_string()));
+            }
+        }
+
+        match value.len() >= 2 && value.starts_with('/') && value.ends_with('/') {
+            true => Regex::new(&value[1..value.len() - 1])
+                .map(OutputPattern::Regex)
+                .map_err(|e| format!("Invalid output regex {}: {}", value, e)),
+            false => Ok(OutputPattern::Text(value.to_string())),
+        }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            OutputPattern::Text(text) => line.contains(text.as_str()),
+            OutputPattern::Regex(regex) => regex.is_match(line),
+        }
+    }
+}
+
+impl PartialEq for OutputPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (OutputPattern::Text(a), OutputPattern::Text(b)) => a == b,
+            (OutputPattern::Regex(a), OutputPattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for OutputPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputPattern::Text(text) => write!(f, "'{}'", text),
+            OutputPattern::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+        }
+    }
+}
+
+/// A request sent repeatedly by an `http` wait instruction until its response matches.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HttpCheck {
+    #[serde(default = "default_method")]
+    pub method: RequestMethod,
+    pub url: String,
+    #[serde(default = "default_status")]
+    pub status: StatusMatcher,
+    #[serde(default)]
+    pub body: MValue,
+    #[serde(
+        rename = "within",
+        default,
+        deserialize_with = "deserialize_optional_timeout"
+    )]
+    pub timeout: Option<Duration>,
+}
+
+fn default_method() -> RequestMethod {
+    RequestMethod::Get
+}
+
+fn default_status() -> StatusMatcher {
+    StatusMatcher::Class(String::from("2xx"))
+}
+
+fn deserialize_optional_timeout<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_timeout(deserializer).map(Some)
+}
+
+pub(crate) fn deserialize_timeout<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timeout {
+        Seconds(f64),
+        Text(String),
+    }
+
+    match Timeout::deserialize(deserializer)? {
+        Timeout::Seconds(seconds) if seconds >= 0.0 => Ok(Duration::from_secs_f64(seconds)),
+        Timeout::Seconds(seconds) => {
+            Err(de::Error::custom(format!("Invalid timeout: {}", seconds)))
+        }
+        Timeout::Text(text) => parse_duration(text.trim()).map_err(de::Error::custom),
+    }
 }
 
 impl<'de> Deserialize<'de> for WaitInstruction {
@@ -35,24 +205,50 @@ impl<'de> Visitor<'de> for WaitInstructionVisitor {
     where
         E: de::Error,
     {
-        match value {
+        let (instruction, timeout) = split_timeout(value.trim()).map_err(E::custom)?;
+
+        match instruction {
             s if s.starts_with("port") => {
-                let port = s
+                let target = s
                     .split(" ")
-                    .skip(1)
-                    .next()
+                    .nth(1)
                     .ok_or(E::custom(String::from("Missing port number.")))?;
+                let (host, port) = match target.rsplit_once(':') {
+                    Some((host, port)) => (host.trim_matches(|c| c == '[' || c == ']'), port),
+                    None => (DEFAULT_HOST, target),
+                };
                 let port = port
-                    .parse::<u64>()
+                    .parse::<u16>()
                     .map_err(|_| E::custom(format!("Invalid port number: {}", port)))?;
 
-                Ok(WaitInstruction::Port(port))
+                Ok(WaitInstruction::Port {
+                    host: host.to_string(),
+                    port,
+                    timeout,
+                })
             }
-            s if s.starts_with("output") => {
-                let output = s.split(" ").skip(1).collect::<Vec<&str>>().join(" ");
-                let output = output.trim_matches(|c| c == '\'' || c == '"').to_string();
+            s if ["any output", "output", "stdout", "stderr"]
+                .iter()
+                .any(|keyword| s.starts_with(keyword)) =>
+            {
+                let (stream, pattern) = match s.strip_prefix("any output") {
+                    Some(pattern) => (OutputStream::Any, pattern.trim_start()),
+                    None => {
+                        let (keyword, pattern) = s.split_once(' ').unwrap_or((s, ""));
+                        let stream = match keyword {
+                            "stderr" => OutputStream::Stderr,
+                            _ => OutputStream::Stdout,
+                        };
+                        (stream, pattern)
+                    }
+                };
+                let pattern = OutputPattern::parse(pattern).map_err(E::custom)?;
 
-                Ok(WaitInstruction::Stdout(output))
+                Ok(WaitInstruction::Output {
+                    stream,
+                    pattern,
+                    timeout,
+                })
             }
             s if s.ends_with("seconds") => {
                 let seconds = s
@@ -64,7 +260,12 @@ impl<'de> Visitor<'de> for WaitInstructionVisitor {
                     .map_err(|_| E::custom(format!("Invalid seconds value: {}", seconds)))?;
                 Ok(WaitInstruction::Seconds(seconds))
             }
-            "finished" | "finish" => Ok(WaitInstruction::Finished),
+            s if s.starts_with("http ") => {
+                let mut check = parse_http_check(s).map_err(E::custom)?;
+                check.timeout = timeout;
+                Ok(WaitInstruction::Http(check))
+            }
+            "finished" | "finish" => Ok(WaitInstruction::Finished(timeout)),
             _ => Err(E::custom(format!("Invalid wait_for instuction: {}", value))),
         }
     }
@@ -82,4 +283,245 @@ impl<'de> Visitor<'de> for WaitInstructionVisitor {
     {
         Ok(WaitInstruction::Seconds(value as f64))
     }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match map.next_key::<String>()?.as_deref() {
+            Some("http") => Ok(WaitInstruction::Http(map.next_value::<HttpCheck>()?)),
+            Some(key) => Err(de::Error::custom(format!(
+                "Invalid wait_for instruction: {}",
+                key
+            ))),
+            None => Err(de::Error::custom("Missing wait_for instruction.")),
+        }
+    }
+}
+
+/// Parses `http [METHOD] <url> [status]`, where the status is either an exact code or a class
+/// like `2xx`.
+fn parse_http_check(value: &str) -> Result<HttpCheck, String> {
+    let mut parts = value.split_whitespace().skip(1).peekable();
+
+    let method = match parts.peek() {
+        Some(part) if !part.contains("://") => {
+            let method = RequestMethod::deserialize(part.into_deserializer())
+                .map_err(|_: de::value::Error| format!("Invalid request method: {}", part))?;
+            parts.next();
+            method
+        }
+        _ => default_method(),
+    };
+
+    let url = parts
+        .next()
+        .ok_or(String::from("Missing url to check."))?
+        .to_string();
+
+    let status = match parts.next() {
+        Some(status) => match status.parse::<u16>() {
+            Ok(code) => StatusMatcher::Exact(code),
+            Err(_) if ["2xx", "3xx", "4xx", "5xx"].contains(&status) => {
+                StatusMatcher::Class(status.to_string())
+            }
+            Err(_) => return Err(format!("Invalid status: {}", status)),
+        },
+        None => default_status(),
+    };
+
+    if let Some(extra) = parts.next() {
+        return Err(format!("Unexpected value after status: {}", extra));
+    }
+
+    Ok(HttpCheck {
+        method,
+        url,
+        status,
+        body: MValue::Null,
+        timeout: None,
+    })
+}
+
+/// Separates a trailing `within` timeout from the rest of the instruction.
+fn split_timeout(value: &str) -> Result<(&str, Option<Duration>), String> {
+    let Some(captures) = TIMEOUT_MATCHER.captures(value) else {
+        return Ok((value, None));
+    };
+
+    let timeout = parse_duration(&captures[2])?;
+    let instruction = captures.get(1).map_or(value, |m| m.as_str());
+    Ok((instruction, Some(timeout)))
+}
+
+/// Parses a duration in seconds or minutes, like `90 seconds` or `2m`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let captures = DURATION_MATCHER
+        .captures(value)
+        .ok_or(format!("Invalid timeout: {}", value))?;
+
+    let amount = captures[1]
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid timeout: {}", value))?;
+    let seconds = match captures.get(2).map(|unit| unit.as_str()) {
+        Some("m" | "minute" | "minutes") => amount * 60.0,
+        _ => amount,
+    };
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(value: &str) -> WaitInstruction {
+        serde_yaml::from_str::<WaitInstruction>(value).unwrap()
+    }
+
+    #[test]
+    fn parses_timeouts() {
+        assert_eq!(
+            parse("port 3000 within 90 seconds"),
+            WaitInstruction::Port {
+                host: String::from("127.0.0.1"),
+                port: 3000,
+                timeout: Some(Duration::from_secs(90)),
+            }
+        );
+        assert_eq!(
+            parse("output 'Listening on 3000' within 2m"),
+            WaitInstruction::Output {
+                stream: OutputStream::Stdout,
+                pattern: OutputPattern::Text(String::from("Listening on 3000")),
+                timeout: Some(Duration::from_secs(120)),
+            }
+        );
+        assert_eq!(
+            parse("finished within 30s"),
+            WaitInstruction::Finished(Some(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            parse("output 'ready within 5 seconds'"),
+            WaitInstruction::Output {
+                stream: OutputStream::Stdout,
+                pattern: OutputPattern::Text(String::from("ready within 5 seconds")),
+                timeout: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_port_hosts() {
+        assert_eq!(
+            parse("port db.internal:5432"),
+            WaitInstruction::Port {
+                host: String::from("db.internal"),
+                port: 5432,
+                timeout: None,
+            }
+        );
+        assert_eq!(
+            parse("port [::1]:3000 within 10 seconds"),
+            WaitInstruction::Port {
+                host: String::from("::1"),
+                port: 3000,
+                timeout: Some(Duration::from_secs(10)),
+            }
+        );
+        assert!(serde_yaml::from_str::<WaitInstruction>("port localhost:http").is_err());
+    }
+
+    #[test]
+    fn parses_output_streams_and_patterns() {
+        assert_eq!(
+            parse("stderr Listening on port 3000"),
+            WaitInstruction::Output {
+                stream: OutputStream::Stderr,
+                pattern: OutputPattern::Text(String::from("Listening on port 3000")),
+                timeout: None,
+            }
+        );
+
+        let WaitInstruction::Output {
+            stream, pattern, ..
+        } = parse(r"stdout /listening on (port )?\d+/ within 10s")
+        else {
+            panic!("Expected an output instruction");
+        };
+        assert_eq!(stream, OutputStream::Stdout);
+        assert!(pattern.is_match("Server listening on port 3000"));
+        assert!(pattern.is_match("listening on 8080"));
+        assert!(!pattern.is_match("listening on port"));
+
+        assert_eq!(
+            parse("output '/health/'"),
+            WaitInstruction::Output {
+                stream: OutputStream::Stdout,
+                pattern: OutputPattern::Text(String::from("/health/")),
+                timeout: None,
+            }
+        );
+        assert_eq!(
+            parse("any output Started in 2.1 seconds"),
+            WaitInstruction::Output {
+                stream: OutputStream::Any,
+                pattern: OutputPattern::Text(String::from("Started in 2.1 seconds")),
+                timeout: None,
+            }
+        );
+        assert!(serde_yaml::from_str::<WaitInstruction>("output /[unclosed/").is_err());
+    }
+
+    #[test]
+    fn parses_http_checks() {
+        assert_eq!(
+            parse("http GET http://localhost:3000/health 200 within 90 seconds"),
+            WaitInstruction::Http(HttpCheck {
+                method: RequestMethod::Get,
+                url: String::from("http://localhost:3000/health"),
+                status: StatusMatcher::Exact(200),
+                body: MValue::Null,
+                timeout: Some(Duration::from_secs(90)),
+            })
+        );
+        assert_eq!(
+            parse("http http://localhost:3000/health"),
+            WaitInstruction::Http(HttpCheck {
+                method: RequestMethod::Get,
+                url: String::from("http://localhost:3000/health"),
+                status: StatusMatcher::Class(String::from("2xx")),
+                body: MValue::Null,
+                timeout: None,
+            })
+        );
+        assert!(serde_yaml::from_str::<WaitInstruction>("http FETCH http://localhost").is_err());
+        assert!(serde_yaml::from_str::<WaitInstruction>("http GET http://localhost ok").is_err());
+    }
+
+    #[test]
+    fn parses_http_check_mappings() {
+        let instruction = parse(
+            r#"
+            http:
+              method: POST
+              url: http://localhost:3000/ready
+              status: 2xx
+              body:
+                status: ready
+              within: 2m
+            "#,
+        );
+
+        let WaitInstruction::Http(check) = instruction else {
+            panic!("Expected an http check, found {:?}", instruction);
+        };
+        assert_eq!(check.method, RequestMethod::Post);
+        assert_eq!(check.status, StatusMatcher::Class(String::from("2xx")));
+        assert_eq!(check.timeout, Some(Duration::from_secs(120)));
+        assert_eq!(
+            check.body,
+            serde_yaml::from_str::<MValue>("status: ready").unwrap()
+        );
+    }
 }
@@ -1,13 +1,35 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::Path,
+};
+
+use colored::Colorize;
+use futures::StreamExt;
 use serde::Deserialize;
+use tokio::sync::Semaphore;
 
 use crate::{
-    client::Client,
+    client::{Client, ClientConfig},
     errors::CaptiError,
-    suite::{report::TestResultsReport, setup::SuiteSetup},
-    variables::{variable_map::VariableMap, SuiteVariables},
+    loader::suite_loader::SuiteLoader,
+    m_value::m_value::MValue,
+    progress_println,
+    runner::{
+        run_config::RunConfig,
+        variable_check::{Resolution, UnresolvedVariable},
+    },
+    suite::{
+        report::TestResultsReport,
+        setup::{suite_setup::SetupContext, Processes, SuiteSetup},
+    },
+    variables::{
+        source::{ResolvedVariable, VariableSource},
+        variable_map::{deserialize_definitions, VariableMap},
+        SuiteVariables, VariableReferences,
+    },
 };
 
-use super::{report::ReportedResult, test::TestDefinition};
+use super::{report::ReportedResult, test::TestDefinition, test_result::TestResult};
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct Suite {
@@ -15,10 +37,17 @@ pub struct Suite {
     description: Option<String>,
     #[serde(default)]
     parallel: bool,
+    max_concurrency: Option<usize>,
     setup: Option<SuiteSetup>,
     tests: Vec<TestDefinition>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_definitions")]
     variables: VariableMap,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub export: Vec<String>,
+    #[serde(default, rename = "client")]
+    client_config: ClientConfig,
     #[serde(skip)]
     client: Client,
 }
@@ -26,79 +55,860 @@ pub struct Suite {
 impl Suite {
     pub fn from_file(path: &str) -> Result<Self, CaptiError> {
         let suite = std::fs::read_to_string(path)?;
-        let suite = serde_yaml::from_str::<Suite>(&suite)?;
-        Ok(suite)
+        SuiteLoader::parse_suite(&suite, Path::new(path))
+    }
+
+    /// Expands any data-driven tests in the suite into their individual test instances. Test data
+    /// file paths are resolved relative to `base_dir`, typically the directory of the suite file.
+    /// Parallel suites can't have tests that clear cookies.
+    pub fn expand_tests(&mut self, base_dir: &Path) -> Result<(), CaptiError> {
+        let mut tests = Vec::new();
+        for test in self.tests.iter() {
+            // Tests in a parallel suite share cookies while they run
+            if self.parallel && test.clears_cookies() {
+                return Err(CaptiError::client_config_error(format!(
+                    "Test '{}' sets 'clear_cookies', which can't be used in a parallel suite.",
+                    &test.test
+                )));
+            }
+
+            tests.extend(test.expand(base_dir)?);
+        }
+
+        self.tests = tests;
+
+        Ok(())
     }
 
     pub fn get_test_count(&self) -> usize {
         self.tests.len()
     }
 
-    pub async fn run(&mut self) -> TestResultsReport {
-        if let Some(setup) = &self.setup {
-            setup.execute_before_all().await;
+    /// Builds the suite's HTTP client from the client settings in the config file, overridden by
+    /// any client settings defined on the suite. The suite's certificate paths are relative to the
+    /// suite file's directory.
+    pub fn configure_client(&mut self, base_dir: &Path) -> Result<(), CaptiError> {
+        self.client_config.resolve_paths(base_dir);
+        let config = RunConfig::global().client.merge(&self.client_config);
+        self.client = Client::build(&config)?;
+
+        Ok(())
+    }
+
+    /// Applies variables from the selected environment and the command line, which take
+    /// precedence over the variables defined by the suite and its tests.
+    pub fn override_variables(&mut self, variables: &VariableMap) {
+        self.variables.extend(variables);
+        for test in self.tests.iter_mut() {
+            test.override_defines(variables);
         }
+    }
 
-        let results = match &self.parallel {
-            true => {
-                for test in self.tests.iter_mut() {
-                    test.populate_variables(&mut self.variables)
-                        .expect("Error populating variables for test.");
-                }
+    /// Makes variables from the run-wide scope available to this suite. Variables defined by the
+    /// suite itself take precedence. Secret variables stay secret.
+    pub fn import_variables(&mut self, variables: &VariableMap) {
+        for (key, value) in variables.iter() {
+            if self.variables.contains_key(key) {
+                continue;
+            }
+
+            match variables.is_secret(key) {
+                true => self.variables.insert_secret(key.clone(), value.clone()),
+                false => self.variables.insert(key.clone(), value.clone()),
+            }
+        }
+    }
+
+    /// Returns the fully resolved values of the variables listed in the suite's `export` field, to
+    /// be published to suites that run afterward. Secret variables are exported as secrets.
+    pub fn exported_variables(&self) -> VariableMap {
+        let mut variables = self.variables.clone();
+        let mut exported = VariableMap::new();
+
+        for name in self.export.iter() {
+            if variables.get(name).is_none() {
+                progress_println!(
+                    "{}: Variable {} exported by suite '{}' is not defined.",
+                    "WARN".yellow(),
+                    name,
+                    &self.suite
+                );
+                continue;
+            }
+
+            let Ok(value) = variables.replace_variables(format!("${{{}}}", name)) else {
+                continue;
+            };
+
+            match self.variables.is_secret(name) {
+                true => exported.insert_secret(name.clone(), value),
+                false => exported.insert(name.clone(), value),
+            }
+        }
 
-                let mut results = vec![];
+        exported
+    }
+
+    /// Lists every variable the suite defines or needs, and every variable its tests define, with
+    /// its final value and where that value comes from.
+    pub fn resolved_variables(&self, imported: &HashSet<String>) -> Vec<ResolvedVariable> {
+        let config = RunConfig::global();
+        let mut variables = self.variables.clone();
+
+        let mut extracted = self
+            .tests
+            .iter()
+            .flat_map(|test| test.extracted_variables())
+            .collect::<HashSet<String>>();
+        extracted.extend(self.setup_variables());
+
+        let mut names = self.variables.keys().cloned().collect::<BTreeSet<String>>();
+        names.extend(
+            self.variables
+                .variable_references("variables")
+                .into_iter()
+                .chain(
+                    self.tests
+                        .iter()
+                        .flat_map(|test| test.required_references()),
+                )
+                .map(|reference| reference.name),
+        );
+
+        let mut resolved = names
+            .into_iter()
+            .map(|name| {
+                let source = match config.variable_sources.get(&name) {
+                    Some(source) => source.clone(),
+                    None if self.variables.contains_key(&name) => VariableSource::Suite,
+                    None if extracted.contains(&name) || imported.contains(&name) => {
+                        VariableSource::Runtime
+                    }
+                    None if std::env::var(&name).is_ok() => VariableSource::Shell,
+                    None => match config.env.source(&name) {
+                        Some(path) => VariableSource::EnvFile(path.to_path_buf()),
+                        None => VariableSource::Undefined,
+                    },
+                };
 
-                for test in &self.tests {
-                    let test_execution = async {
-                        if let Some(setup) = &self.setup {
-                            setup.execute_before_each().await;
-                        }
+                let value = match source {
+                    VariableSource::Runtime | VariableSource::Undefined => None,
+                    _ => variables.replace_variables(format!("${{{}}}", name)).ok(),
+                };
 
-                        let reported_result = test.execute(&self.client, &self.suite, None).await;
+                ResolvedVariable {
+                    name,
+                    value,
+                    source,
+                }
+            })
+            .collect::<Vec<ResolvedVariable>>();
 
-                        if let Some(setup) = &self.setup {
-                            setup.execute_after_each().await;
-                        }
+        for test in self.tests.iter() {
+            let mut defines = test.defines().iter().collect::<Vec<(&String, &MValue)>>();
+            defines.sort_by_key(|(name, _)| *name);
 
-                        return reported_result;
-                    };
-                    results.push(test_execution);
+            // Overridden definitions are already listed with the suite variables
+            for (name, value) in defines {
+                if config.variable_sources.contains_key(name) {
+                    continue;
                 }
 
-                futures::future::join_all(results).await
+                resolved.push(ResolvedVariable {
+                    name: name.clone(),
+                    value: variables.replace_variables(value.clone()).ok(),
+                    source: VariableSource::Define(test.test.clone()),
+                });
             }
-            false => {
-                let mut results = vec![];
-                for test in self.tests.iter_mut() {
-                    if let Err(e) = test.populate_variables(&mut self.variables) {
-                        let reported_result = ReportedResult::new(test, Err(e));
-                        results.push(reported_result);
-                        continue;
-                    }
+        }
 
-                    if let Some(setup) = &self.setup {
-                        setup.execute_before_each().await;
-                    }
-                    let reported_result = test
-                        .execute(&self.client, &self.suite, Some(&mut self.variables))
-                        .await;
+        resolved
+    }
 
-                    if let Some(setup) = &self.setup {
-                        setup.execute_after_each().await;
-                    }
+    /// Finds the variable references in the suite that aren't defined by the suite, the
+    /// variables imported from other suites, the environment, or the env file. Each is reported
+    /// with the test that extracts it, if any.
+    pub fn unresolved_variables(&self, imported: &HashSet<String>) -> Vec<UnresolvedVariable> {
+        let extracted = self
+            .tests
+            .iter()
+            .map(|test| test.extracted_variables())
+            .collect::<Vec<HashSet<String>>>();
+        let setup_variables = self.setup_variables();
+
+        let resolve = |name: &str, position: usize| {
+            let defined = self.variables.contains_key(name)
+                || imported.contains(name)
+                || setup_variables.contains(name)
+                || std::env::var(name).is_ok()
+                || RunConfig::global().env.contains_key(name);
+
+            if defined {
+                return None;
+            }
+
+            let extracted_by = |range: std::ops::Range<usize>| {
+                range
+                    .into_iter()
+                    .find(|i| extracted[*i].contains(name))
+                    .map(|i| self.tests[i].test.clone())
+            };
+
+            let resolution = match extracted_by(0..position) {
+                Some(test) => Resolution::Extracted(test),
+                None => match extracted_by(position..self.tests.len()) {
+                    Some(test) => Resolution::ExtractedLater(test),
+                    None => Resolution::Undefined,
+                },
+            };
+
+            Some(resolution)
+        };
+
+        // Suite variables are resolved when they are used, so any test may extract them
+        let mut unresolved = self
+            .variables
+            .variable_references("variables")
+            .into_iter()
+            .filter(|reference| !reference.optional)
+            .filter_map(|reference| {
+                resolve(&reference.name, self.tests.len()).map(|resolution| UnresolvedVariable {
+                    suite: self.suite.clone(),
+                    test: None,
+                    reference,
+                    resolution,
+                })
+            })
+            .collect::<Vec<UnresolvedVariable>>();
 
-                    results.push(reported_result);
+        for (j, test) in self.tests.iter().enumerate() {
+            for reference in test.required_references() {
+                if let Some(resolution) = resolve(&reference.name, j) {
+                    unresolved.push(UnresolvedVariable {
+                        suite: self.suite.clone(),
+                        test: Some(test.test.clone()),
+                        reference,
+                        resolution,
+                    });
                 }
-                results
             }
+        }
+
+        unresolved
+    }
+
+    /// Returns the names of the variables extracted by the suite's `before_all` hook and by the
+    /// config file's `before_all` hook, and the ports published by Docker Compose.
+    fn setup_variables(&self) -> HashSet<String> {
+        let config = RunConfig::global();
+
+        [&self.setup, &config.setup]
+            .into_iter()
+            .flatten()
+            .flat_map(|setup| setup.extracted_variables())
+            .chain(
+                config
+                    .compose
+                    .iter()
+                    .flat_map(|compose| compose.port_variables().cloned()),
+            )
+            .collect()
+    }
+
+    /// Reports every test in the suite as an error without running it, for suites whose
+    /// dependencies could not be satisfied.
+    pub fn skip(&self, reason: impl Into<String>) -> TestResultsReport {
+        let reason = reason.into();
+        progress_println!(
+            "{}: Skipping suite '{}'. {}",
+            "WARN".yellow(),
+            &self.suite,
+            &reason
+        );
+
+        let results = self
+            .tests
+            .iter()
+            .map(|test| ReportedResult::new(test, Err(CaptiError::dependency_error(&reason))))
+            .collect();
+
+        TestResultsReport::new(&self.suite, results)
+    }
+
+    /// Reports every test in the suite as an error without running it, because a setup hook that
+    /// runs before them failed.
+    pub fn setup_failed(&self, hook: &str) -> TestResultsReport {
+        let results = self
+            .tests
+            .iter()
+            .map(|test| {
+                let error =
+                    CaptiError::setup_error(format!("'{}' did not complete successfully.", hook));
+                ReportedResult::new(test, Err(error))
+            })
+            .collect();
+
+        TestResultsReport::new(&self.suite, results)
+    }
+
+    /// Runs every test in the suite. Parallel tests are limited to the suite's `max_concurrency`,
+    /// and every test waits for a permit from the run-wide request limit, if there is one, before
+    /// sending its request. Background processes started by the suite's setup are tracked in
+    /// `processes`, and are left for the caller to stop.
+    pub async fn run(
+        &mut self,
+        requests: Option<&Semaphore>,
+        processes: &Processes,
+    ) -> TestResultsReport {
+        self.variables.resolve_generators();
+
+        let before_all = match &self.setup {
+            Some(setup) => {
+                let context = SetupContext::new(processes, &self.client, &mut self.variables);
+                setup.execute_before_all(context).await
+            }
+            None => Ok(()),
         };
+        let set_up = before_all.is_ok();
 
-        let report = TestResultsReport::new(&self.suite, results);
+        let mut report = match before_all {
+            Ok(()) => {
+                let results = match &self.parallel {
+                    true => self.run_parallel(requests, processes).await,
+                    false => self.run_sequential(requests, processes).await,
+                };
+                TestResultsReport::new(&self.suite, results)
+            }
+            Err(e) => {
+                progress_println!("{}: [{}] {}", "ERROR".red(), &self.suite, e);
+                self.setup_failed("before_all")
+            }
+        };
 
         if let Some(setup) = &self.setup {
-            setup.execute_after_all().await;
+            if report.failed > 0 || report.errors > 0 {
+                let context = SetupContext::new(processes, &self.client, &mut self.variables);
+                if let Err(e) = setup.execute_on_failure(context).await {
+                    progress_println!("{}: [{}] {}", "ERROR".red(), &self.suite, e);
+                    report.add_setup_error("on_failure", e);
+                }
+            }
+
+            if set_up {
+                let context = SetupContext::new(processes, &self.client, &mut self.variables);
+                if let Err(e) = setup.execute_after_all(context).await {
+                    progress_println!("{}: [{}] {}", "ERROR".red(), &self.suite, e);
+                    report.add_setup_error("after_all", e);
+                }
+            }
+
+            let context = SetupContext::new(processes, &self.client, &mut self.variables);
+            if let Err(e) = setup.execute_always(context).await {
+                progress_println!("{}: [{}] {}", "ERROR".red(), &self.suite, e);
+                report.add_setup_error("always", e);
+            }
         }
 
         return report;
     }
+
+    /// Runs the suite's tests one at a time, in order. Each test can use the variables extracted
+    /// by the tests before it.
+    async fn run_sequential(
+        &mut self,
+        requests: Option<&Semaphore>,
+        processes: &Processes,
+    ) -> Vec<ReportedResult> {
+        let mut results = vec![];
+        for test in self.tests.iter_mut() {
+            let reported_result = run_test(
+                test,
+                &self.setup,
+                &self.client,
+                &self.suite,
+                processes,
+                requests,
+                &mut self.variables,
+            )
+            .await;
+            results.push(reported_result);
+        }
+        results
+    }
+
+    /// Runs the suite's tests concurrently, in the order determined by `parallel_schedule`. Each
+    /// test resolves its variables from the suite variables available when its level starts, and
+    /// any variables it extracts are published to the suite for tests in later levels.
+    async fn run_parallel(
+        &mut self,
+        requests: Option<&Semaphore>,
+        processes: &Processes,
+    ) -> Vec<ReportedResult> {
+        let (levels, dependencies) = self.parallel_schedule();
+        let mut results = self
+            .tests
+            .iter()
+            .map(|_| None)
+            .collect::<Vec<Option<ReportedResult>>>();
+
+        for level in levels {
+            let mut runnable = Vec::new();
+
+            for j in level {
+                let failed_dependency = dependencies[j].iter().find(|i| {
+                    !matches!(
+                        results[**i],
+                        Some(ReportedResult {
+                            result: Ok(TestResult::Passed),
+                            ..
+                        })
+                    )
+                });
+
+                if let Some(i) = failed_dependency {
+                    let error = CaptiError::dependency_error(format!(
+                        "Test '{}' extracts variables used by this test, but did not pass.",
+                        &self.tests[*i].test
+                    ));
+                    results[j] = Some(ReportedResult::new(&self.tests[j], Err(error)));
+                    continue;
+                }
+
+                runnable.push((j, self.variables.clone()));
+            }
+
+            let limit = self.max_concurrency.unwrap_or(runnable.len()).max(1);
+            let (tests, setup, client, suite) =
+                (&self.tests, &self.setup, &self.client, &self.suite);
+
+            let executions = runnable.into_iter().map(|(j, mut variables)| async move {
+                let mut test = tests[j].clone();
+                let reported_result = run_test(
+                    &mut test,
+                    setup,
+                    client,
+                    suite,
+                    processes,
+                    requests,
+                    &mut variables,
+                )
+                .await;
+
+                (j, reported_result, variables)
+            });
+
+            let completed = futures::stream::iter(executions)
+                .buffered(limit)
+                .collect::<Vec<(usize, ReportedResult, VariableMap)>>()
+                .await;
+
+            for (j, reported_result, variables) in completed {
+                for name in self.tests[j].extracted_variables() {
+                    if let Some(value) = variables.get(&name) {
+                        self.variables.insert(name, value);
+                    }
+                }
+
+                results[j] = Some(reported_result);
+            }
+        }
+
+        results.into_iter().flatten().collect()
+    }
+
+    /// Orders the suite's tests for parallel execution. A test that uses a variable extracted by an
+    /// earlier test depends on the most recent earlier test that extracts it, and each level
+    /// contains the tests whose dependencies are all in earlier levels. Returns the levels along
+    /// with the dependencies of each test.
+    fn parallel_schedule(&self) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+        let extracted = self
+            .tests
+            .iter()
+            .map(|test| test.extracted_variables())
+            .collect::<Vec<HashSet<String>>>();
+
+        let mut dependencies = Vec::new();
+        let mut depths = Vec::new();
+
+        for (j, test) in self.tests.iter().enumerate() {
+            let test_dependencies = test
+                .consumed_variables()
+                .iter()
+                .filter_map(|name| (0..j).rev().find(|i| extracted[*i].contains(name)))
+                .collect::<BTreeSet<usize>>()
+                .into_iter()
+                .collect::<Vec<usize>>();
+
+            let depth = test_dependencies
+                .iter()
+                .map(|i| depths[*i] + 1)
+                .max()
+                .unwrap_or(0);
+
+            depths.push(depth);
+            dependencies.push(test_dependencies);
+        }
+
+        let mut levels = Vec::new();
+        for (j, depth) in depths.into_iter().enumerate() {
+            if levels.len() <= depth {
+                levels.resize(depth + 1, Vec::new());
+            }
+            levels[depth].push(j);
+        }
+
+        (levels, dependencies)
+    }
+}
+
+/// Runs a single test along with the suite's `before_each` and `after_each` hooks and the test's
+/// own `before` and `after` hooks. The test's request waits for a permit from the run-wide request
+/// limit, if there is one. Hooks that run before the test run before its variables are
+/// filled in, so the test can use the variables they extract.
+async fn run_test(
+    test: &mut TestDefinition,
+    setup: &Option<SuiteSetup>,
+    client: &Client,
+    suite: &str,
+    processes: &Processes,
+    requests: Option<&Semaphore>,
+    variables: &mut VariableMap,
+) -> ReportedResult {
+    if let Some(setup) = setup {
+        let context = SetupContext::new(processes, client, variables);
+        if let Err(e) = setup.execute_before_each(context).await {
+            progress_println!("{}: [{}] {}", "ERROR".red(), suite, e);
+            return ReportedResult::new(test, Err(e));
+        }
+    }
+
+    let context = SetupContext::new(processes, client, variables);
+    let mut reported_result = match test.execute_before(context).await {
+        Ok(()) => {
+            // Held only while the request is in flight, so waiting hooks don't hold up other tests
+            let permit = match requests {
+                Some(requests) => requests.acquire().await.ok(),
+                None => None,
+            };
+
+            let reported_result = match test.populate_variables(variables) {
+                Ok(()) => test.execute(client, suite, variables).await,
+                Err(e) => ReportedResult::new(test, Err(e)),
+            };
+
+            drop(permit);
+
+            let context = SetupContext::new(processes, client, variables);
+            match test.execute_after(context).await {
+                Ok(()) => reported_result,
+                Err(e) => after_hook_failed(suite, reported_result, e),
+            }
+        }
+        Err(e) => {
+            progress_println!("{}: [{}] {}", "ERROR".red(), suite, e);
+            ReportedResult::new(test, Err(e))
+        }
+    };
+
+    if let Some(setup) = setup {
+        let context = SetupContext::new(processes, client, variables);
+        if let Err(e) = setup.execute_after_each(context).await {
+            reported_result = after_hook_failed(suite, reported_result, e);
+        }
+    }
+
+    reported_result
+}
+
+/// Reports an `after_each` or `after` hook that failed. A test that passed is reported as an
+/// error instead, while a test that failed keeps its own failure.
+fn after_hook_failed(
+    suite: &str,
+    reported_result: ReportedResult,
+    error: CaptiError,
+) -> ReportedResult {
+    progress_println!("{}: [{}] {}", "ERROR".red(), suite, error);
+
+    match reported_result.result {
+        Ok(TestResult::Passed) => ReportedResult {
+            result: Err(error),
+            ..reported_result
+        },
+        _ => reported_result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn schedules_consumers_after_producers() {
+        let yaml = r#"
+        suite: Parallel extraction
+        parallel: true
+        tests:
+          - test: Create user
+            request:
+              method: POST
+              url: ${BASE_URL}/users
+            expect:
+              status: 201
+            extract:
+              body:
+                id: ${USER_ID}
+          - test: List recipes
+            request:
+              method: GET
+              url: ${BASE_URL}/recipes
+            expect:
+              status: 200
+          - test: Get user
+            request:
+              method: GET
+              url: ${BASE_URL}/users/${USER_ID}
+            expect:
+              status: 200
+        "#;
+
+        let suite = serde_yaml::from_str::<Suite>(yaml).unwrap();
+        let (levels, dependencies) = suite.parallel_schedule();
+
+        assert_eq!(levels, vec![vec![0, 1], vec![2]]);
+        assert_eq!(dependencies, vec![vec![], vec![], vec![0]]);
+    }
+
+    #[test]
+    fn exports_secret_variables_as_secrets() {
+        let yaml = r#"
+        suite: Sign in
+        variables:
+          SESSION:
+            value: exported-s3cret
+            secret: true
+        export: [SESSION]
+        tests: []
+        "#;
+
+        let suite = serde_yaml::from_str::<Suite>(yaml).unwrap();
+        let exports = suite.exported_variables();
+        assert!(exports.is_secret("SESSION"));
+
+        let yaml = r#"
+        suite: Orders
+        depends_on: [Sign in]
+        tests: []
+        "#;
+
+        let mut dependent = serde_yaml::from_str::<Suite>(yaml).unwrap();
+        dependent.import_variables(&exports);
+        assert!(dependent.variables.is_secret("SESSION"));
+    }
+
+    #[test]
+    fn rejects_clearing_cookies_in_parallel_suites() {
+        let yaml = r#"
+        suite: Parallel cookies
+        parallel: true
+        tests:
+          - test: Signed out
+            clear_cookies: true
+            request:
+              method: GET
+              url: http://localhost:3000/recipes
+            expect:
+              status: 401
+        "#;
+
+        let mut suite = serde_yaml::from_str::<Suite>(yaml).unwrap();
+        assert!(suite.expand_tests(Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn finds_unresolved_variables() {
+        let yaml = r#"
+        suite: Unresolved variables
+        variables:
+          BASE_URL: http://localhost:3000
+        tests:
+          - test: Get profile
+            request:
+              method: GET
+              url: ${BASE_ULR}/users/${USER_ID}
+            expect:
+              status: 200
+          - test: Create user
+            request:
+              method: POST
+              url: ${BASE_URL}/users/${uuid()}?page=${PAGE:-1}
+            expect:
+              status: 201
+            extract:
+              body:
+                id: ${USER_ID}
+          - test: Get user
+            define:
+              FIELD: name
+            request:
+              method: GET
+              url: ${BASE_URL}/users/${USER_ID}/${FIELD}
+            expect:
+              status: 200
+        "#;
+
+        let suite = serde_yaml::from_str::<Suite>(yaml).unwrap();
+        let unresolved = suite
+            .unresolved_variables(&HashSet::new())
+            .into_iter()
+            .map(|variable| (variable.test, variable.reference.name, variable.resolution))
+            .collect::<Vec<(Option<String>, String, Resolution)>>();
+
+        assert_eq!(
+            unresolved,
+            vec![
+                (
+                    Some(String::from("Get profile")),
+                    String::from("BASE_ULR"),
+                    Resolution::Undefined
+                ),
+                (
+                    Some(String::from("Get profile")),
+                    String::from("USER_ID"),
+                    Resolution::ExtractedLater(String::from("Create user"))
+                ),
+                (
+                    Some(String::from("Get user")),
+                    String::from("USER_ID"),
+                    Resolution::Extracted(String::from("Create user"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides_suite_variables_and_defines() {
+        let mut suite = serde_yaml::from_str::<Suite>(
+            r#"
+        suite: Overrides
+        variables:
+          BASE_URL: http://localhost:3000
+          USER: alice
+        tests:
+          - test: Get build
+            define:
+              BUILD: local
+              PAGE: 1
+            request:
+              method: GET
+              url: ${BASE_URL}/builds/${BUILD}?page=${PAGE}
+            expect:
+              status: 200
+        "#,
+        )
+        .unwrap();
+
+        let mut overrides = VariableMap::new();
+        overrides.insert("BASE_URL", "https://staging.example.com");
+        overrides.insert("BUILD", "1234");
+        suite.override_variables(&overrides);
+
+        assert_eq!(
+            suite.variables["BASE_URL"],
+            MValue::String(String::from("https://staging.example.com"))
+        );
+        assert_eq!(
+            suite.variables["USER"],
+            MValue::String(String::from("alice"))
+        );
+        assert_eq!(
+            suite.tests[0].defines()["BUILD"],
+            MValue::String(String::from("1234"))
+        );
+        assert_eq!(suite.tests[0].defines()["PAGE"], MValue::Number(1.into()));
+    }
+
+    #[tokio::test]
+    async fn runs_failure_hooks_after_setup_errors() {
+        let dir = std::env::temp_dir().join(format!("capti-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let marker = |name: &str| dir.join(name).display().to_string();
+
+        let yaml = format!(
+            r#"
+        suite: Failure hooks
+        setup:
+          before_all:
+            - script: exit 1
+              wait_until: finished
+          on_failure:
+            - script: touch {}
+              wait_until: finished
+          after_all:
+            - script: touch {}
+              wait_until: finished
+          always:
+            - script: touch {}
+              wait_until: finished
+        tests:
+          - test: Never runs
+            request:
+              method: GET
+              url: http://127.0.0.1:1/
+            expect:
+              status: 200
+        "#,
+            marker("on_failure"),
+            marker("after_all"),
+            marker("always"),
+        );
+
+        let mut suite = serde_yaml::from_str::<Suite>(&yaml).unwrap();
+        let report = suite.run(None, &Processes::default()).await;
+        let ran = ["on_failure", "after_all", "always"].map(|hook| dir.join(hook).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(report.errors, 1);
+        assert_eq!(ran, [true, false, true]);
+    }
+
+    #[tokio::test]
+    async fn runs_test_hooks_around_each_test() {
+        let dir = std::env::temp_dir().join(format!("capti-test-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("hooks.log").display().to_string();
+
+        let yaml = format!(
+            r#"
+        suite: Test hooks
+        setup:
+          before_each:
+            - script: echo before_each >> {log}
+              wait_until: finished
+          after_each:
+            - script: echo after_each >> {log}
+              wait_until: finished
+        tests:
+          - test: Fails in before hook
+            before:
+              - script: echo before >> {log}; exit 1
+                wait_until: finished
+            after:
+              - script: echo after >> {log}
+                wait_until: finished
+            request:
+              method: GET
+              url: http://127.0.0.1:1/
+            expect:
+              status: 200
+        "#,
+        );
+
+        let mut suite = serde_yaml::from_str::<Suite>(&yaml).unwrap();
+        let report = suite.run(None, &Processes::default()).await;
+        let hooks = std::fs::read_to_string(dir.join("hooks.log")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(report.errors, 1);
+        assert_eq!(hooks, "before_each\nbefore\nafter_each\n");
+    }
 }
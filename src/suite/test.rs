@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{collections::HashSet, fmt::Debug, path::Path};
 
 use serde::Deserialize;
 
@@ -9,12 +9,21 @@ use crate::{
     m_value::match_context::MatchContext,
     progress::Spinner,
     progress_println,
-    variables::{variable_map::VariableMap, SuiteVariables},
+    variables::{
+        variable_map::{deserialize_definitions, VariableMap},
+        SuiteVariables, VariableReference, VariableReferences,
+    },
 };
 
 use super::{
-    extract::ResponseExtractor, failure_report::FailureReport, report::ReportedResult,
-    request::RequestDefinition, response::ResponseDefinition, test_result::TestResult,
+    data::TestData,
+    extract::ResponseExtractor,
+    failure_report::FailureReport,
+    report::ReportedResult,
+    request::RequestDefinition,
+    response::{Redirect, Redirects, ResponseDefinition},
+    setup::suite_setup::{SetupContext, SetupInstruction, SuiteSetup},
+    test_result::TestResult,
 };
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
@@ -28,16 +37,132 @@ pub struct TestDefinition {
     pub extract: Option<ResponseExtractor>,
     #[serde(default)]
     print_response: bool,
+    follow_redirects: Option<bool>,
     #[serde(default)]
+    clear_cookies: bool,
+    #[serde(default, deserialize_with = "deserialize_definitions")]
     define: VariableMap,
+    #[serde(alias = "matrix")]
+    each: Option<TestData>,
+    /// Setup steps run before this test, after the suite's `before_each` hook.
+    before: Option<Vec<SetupInstruction>>,
+    /// Setup steps run after this test, before the suite's `after_each` hook.
+    after: Option<Vec<SetupInstruction>>,
 }
 
 impl TestDefinition {
+    /// Expands a data-driven test into one test instance per set of variables in its `each` data.
+    /// Each instance defines its variables locally, so they take precedence over suite variables.
+    /// Tests without `each` data are returned unchanged, while tests with empty `each` data are an
+    /// error, since they would never run.
+    pub fn expand(&self, base_dir: &Path) -> Result<Vec<TestDefinition>, CaptiError> {
+        let data = match &self.each {
+            Some(data) => data.load(base_dir)?,
+            None => return Ok(vec![self.clone()]),
+        };
+
+        if data.is_empty() {
+            return Err(CaptiError::test_data_error(format!(
+                "The test data for '{}' is empty, so the test would never run.",
+                &self.test
+            )));
+        }
+
+        let tests = data
+            .into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut test = self.clone();
+                test.each = None;
+                test.test = format!("{} [{}]", &self.test, i + 1);
+                for (key, value) in row.iter() {
+                    test.define.insert(key.clone(), value.clone());
+                }
+                test
+            })
+            .collect::<Vec<TestDefinition>>();
+
+        Ok(tests)
+    }
+
+    /// Replaces locally defined variables whose values are overridden for the whole run. Variables
+    /// from `each` data are added afterwards, so they are always used as given.
+    pub fn override_defines(&mut self, variables: &VariableMap) {
+        for (key, value) in variables.iter() {
+            if self.define.contains_key(key) {
+                self.define.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    pub fn clears_cookies(&self) -> bool {
+        self.clear_cookies
+    }
+
+    pub fn defines(&self) -> &VariableMap {
+        &self.define
+    }
+
+    pub async fn execute_before(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("before", &self.before, context).await
+    }
+
+    pub async fn execute_after(&self, context: SetupContext<'_>) -> Result<(), CaptiError> {
+        SuiteSetup::execute_hook("after", &self.after, context).await
+    }
+
+    /// Returns true if the variable is defined locally, or is extracted by the test's `before`
+    /// hook.
+    fn defines_locally(&self, name: &str) -> bool {
+        self.define.contains_key(name)
+            || self
+                .before
+                .iter()
+                .flatten()
+                .any(|instruction| instruction.extracted_variables().contains(name))
+    }
+
+    /// Returns the names of the variables this test uses that it does not define locally.
+    pub fn consumed_variables(&self) -> HashSet<String> {
+        self.variable_references("")
+            .into_iter()
+            .map(|reference| reference.name)
+            .filter(|name| !self.defines_locally(name))
+            .collect()
+    }
+
+    /// Returns the references this test needs resolved from outside the test: those that aren't
+    /// defined locally and don't have a default value.
+    pub fn required_references(&self) -> Vec<VariableReference> {
+        let mut seen = HashSet::new();
+
+        self.variable_references("")
+            .into_iter()
+            .filter(|reference| !reference.optional && !self.defines_locally(&reference.name))
+            .filter(|reference| seen.insert((reference.field.clone(), reference.name.clone())))
+            .collect()
+    }
+
+    /// Returns the names of the variables this test extracts from its response, or that its hooks
+    /// extract.
+    pub fn extracted_variables(&self) -> HashSet<String> {
+        let mut names = match &self.extract {
+            Some(extractor) => extractor.variable_names(),
+            None => HashSet::new(),
+        };
+
+        for instruction in self.before.iter().chain(self.after.iter()).flatten() {
+            names.extend(instruction.extracted_variables());
+        }
+
+        names
+    }
+
     pub async fn execute(
         &self,
         client: &Client,
         suite: &str,
-        variables: Option<&mut VariableMap>,
+        variables: &mut VariableMap,
     ) -> ReportedResult {
         let spinner = Spinner::start(format!("[{}] {}", &suite, &self.test)).await;
 
@@ -52,12 +177,9 @@ impl TestDefinition {
     async fn process(
         &self,
         client: &Client,
-        variables: Option<&mut VariableMap>,
+        variables: &mut VariableMap,
     ) -> Result<TestResult, CaptiError> {
-        let request = self.request.build_client_request(&client)?;
-        let response = request.send().await?;
-
-        let response = ResponseDefinition::from_response(response).await;
+        let response = self.send(client).await?;
 
         if self.print_response {
             let title = format!("Response: ({})", &self.test);
@@ -89,15 +211,86 @@ impl TestDefinition {
         }
 
         if let Some(extractor) = &self.extract {
-            if let Some(variables) = variables {
-                extractor.extract(&response, variables).await?;
-            } else {
-                return Err(CaptiError::parallel_error("Cannot extract variables from tests running in parallel. Try setting the suite to 'parallel: false'"));
-            }
+            extractor.extract(&response, variables).await?;
         }
 
         Ok(test_result)
     }
+
+    /// Sends the test's request. Requests using digest authentication are sent a second time in
+    /// response to the server's challenge.
+    async fn send(&self, client: &Client) -> Result<ResponseDefinition, CaptiError> {
+        if self.clear_cookies {
+            client.clear_cookies();
+        }
+
+        let (response, redirects) = self.send_request(client, None).await?;
+
+        let (response, redirects) = match self.request.digest_authorization(&response)? {
+            Some(authorization) => self.send_request(client, Some(authorization)).await?,
+            None => (response, redirects),
+        };
+
+        let mut response = ResponseDefinition::from_response(response).await;
+        response.redirects = redirects;
+
+        Ok(response)
+    }
+
+    /// Redirects are followed by the client unless the test disables them, and are followed
+    /// manually when the test makes assertions on the redirect chain.
+    async fn send_request(
+        &self,
+        client: &Client,
+        authorization: Option<String>,
+    ) -> Result<(reqwest::Response, Option<Redirects>), CaptiError> {
+        let follow_redirects = self.follow_redirects != Some(false);
+
+        let request = self
+            .request
+            .build_request(client, follow_redirects, authorization)
+            .await?;
+
+        if !follow_redirects {
+            let response = client.without_redirects().execute(request).await?;
+            return Ok((response, Some(Redirects::default())));
+        }
+
+        if self.expect.redirects.is_none() {
+            return Ok((client.execute(request).await?, None));
+        }
+
+        let (response, redirects) = client.send_recording_redirects(request).await?;
+        let redirects = redirects
+            .into_iter()
+            .map(|(status, location)| Redirect::new(status, location))
+            .collect::<Vec<Redirect>>();
+
+        Ok((response, Some(redirects.into())))
+    }
+}
+
+impl VariableReferences for TestDefinition {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        let prefix = match field {
+            "" => String::new(),
+            field => format!("{}.", field),
+        };
+
+        let mut references = self
+            .request
+            .variable_references(&format!("{}request", prefix));
+        references.extend(
+            self.expect
+                .variable_references(&format!("{}expect", prefix)),
+        );
+        references.extend(
+            self.define
+                .variable_references(&format!("{}define", prefix)),
+        );
+
+        references
+    }
 }
 
 impl SuiteVariables for TestDefinition {
@@ -132,27 +325,104 @@ mod test {
             headers: MHeaders::default(),
             body: MValue::default(),
             status: Status::none(),
+            cookies: None,
+            redirects: None,
         };
         let response = ResponseDefinition {
             headers: MHeaders::default(),
             body: serde_json::from_str::<MValue>(r#"{"test": "test"}"#).unwrap(),
             status: Status::from(200),
+            cookies: None,
+            redirects: None,
         };
 
         assert_eq!(matcher.compare(&response).unwrap(), TestResult::Passed);
     }
 
+    #[test]
+    fn expands_data_driven_tests() {
+        let yaml = r#"
+        test: Invalid signup
+        request:
+          method: POST
+          url: http://localhost:3000/signup
+          body:
+            email: ${EMAIL}
+        expect:
+          status: 400
+        each:
+          - EMAIL: invalid
+          - EMAIL: ""
+        "#;
+
+        let test = serde_yaml::from_str::<TestDefinition>(yaml).unwrap();
+        let tests = test.expand(Path::new(".")).unwrap();
+
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].test, "Invalid signup [1]");
+        assert_eq!(tests[1].test, "Invalid signup [2]");
+        assert_eq!(tests[0].define["EMAIL"], MValue::String("invalid".into()));
+        assert!(tests[0].each.is_none());
+    }
+
+    #[test]
+    fn masks_secret_defines() {
+        let yaml = r#"
+        test: Sign in
+        request:
+          method: POST
+          url: http://localhost:3000/signin
+          body:
+            password: ${PASSWORD}
+        expect:
+          status: 200
+        define:
+          PASSWORD:
+            value: define-s3cret
+            secret: true
+        "#;
+
+        let mut test = serde_yaml::from_str::<TestDefinition>(yaml).unwrap();
+        assert!(test.define.is_secret("PASSWORD"));
+
+        test.populate_variables(&mut VariableMap::new()).unwrap();
+        assert_eq!(
+            crate::variables::secrets::redact("password=define-s3cret"),
+            format!("password={}", crate::variables::secrets::MASK)
+        );
+    }
+
+    #[test]
+    fn rejects_empty_test_data() {
+        let yaml = r#"
+        test: Invalid signup
+        request:
+          method: POST
+          url: http://localhost:3000/signup
+        expect:
+          status: 400
+        each: []
+        "#;
+
+        let test = serde_yaml::from_str::<TestDefinition>(yaml).unwrap();
+        assert!(test.expand(Path::new(".")).is_err());
+    }
+
     #[test]
     fn test_compare_status_matches() {
         let matcher = ResponseDefinition {
             headers: MHeaders::default(),
             body: MValue::Null,
             status: Status::from("2xx"),
+            cookies: None,
+            redirects: None,
         };
         let response = ResponseDefinition {
             headers: MHeaders::default(),
             body: MValue::Null,
             status: Status::from(200),
+            cookies: None,
+            redirects: None,
         };
 
         assert_eq!(matcher.compare(&response).unwrap(), TestResult::Passed);
@@ -0,0 +1,730 @@
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+use serde_yaml::Number;
+
+use crate::{errors::CaptiError, m_value::m_value::MValue};
+
+use super::{filters, generators};
+
+/// Looks up the value of a variable by name, returning `None` if it is not defined.
+pub type Lookup<'a> = dyn FnMut(&str) -> Result<Option<MValue>, CaptiError> + 'a;
+
+/// A parsed `${...}` expression, such as `${USER_ID | upper}`, `${ids[0]}`, `${price * 2}`, or
+/// `${PORT:-8080}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Variable(String),
+    Literal(MValue),
+    Call(String, Vec<String>),
+    Field(Box<Expression>, String),
+    Index(Box<Expression>, Box<Expression>),
+    Negate(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+    Filter(Box<Expression>, String, Vec<String>),
+    Default(Box<Expression>, MValue),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(Number),
+    Str(String),
+    Call(String, Vec<String>),
+    Dot,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Pipe,
+    Operator(Operator),
+}
+
+impl Expression {
+    /// Parses the text between `${` and `}`.
+    pub fn parse(source: &str) -> Result<Expression, CaptiError> {
+        let (source, default) = match split_default(source) {
+            Some((source, default)) => (source, Some(parse_default(default))),
+            None => (source, None),
+        };
+
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expression = parser.parse_pipe()?;
+
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(CaptiError::variable_error(format!(
+                "Unexpected '{}' in expression: {}",
+                token, source
+            )));
+        }
+
+        Ok(match default {
+            Some(default) => Expression::Default(Box::new(expression), default),
+            None => expression,
+        })
+    }
+
+    /// Evaluates the expression, returning `None` if a variable, field, or index it depends on
+    /// does not exist.
+    pub fn evaluate(&self, lookup: &mut Lookup) -> Result<Option<MValue>, CaptiError> {
+        let value = match self {
+            Expression::Variable(name) => lookup(name)?,
+            Expression::Literal(value) => Some(value.clone()),
+            Expression::Call(name, args) => Some(generators::call(name, args)?),
+            Expression::Field(target, field) => match target.evaluate(lookup)? {
+                Some(target) => field_value(&target, field),
+                None => None,
+            },
+            Expression::Index(target, index) => {
+                match (target.evaluate(lookup)?, index.evaluate(lookup)?) {
+                    (Some(target), Some(index)) => index_value(&target, &index),
+                    _ => None,
+                }
+            }
+            Expression::Negate(value) => match value.evaluate(lookup)? {
+                Some(value) => Some(arithmetic(
+                    &MValue::Number(Number::from(0)),
+                    Operator::Subtract,
+                    &value,
+                )?),
+                None => None,
+            },
+            Expression::Binary(left, operator, right) => {
+                match (left.evaluate(lookup)?, right.evaluate(lookup)?) {
+                    (Some(left), Some(right)) => Some(arithmetic(&left, *operator, &right)?),
+                    _ => None,
+                }
+            }
+            Expression::Filter(value, name, args) => match value.evaluate(lookup)? {
+                Some(value) => Some(filters::apply(name, args, value)?),
+                None => None,
+            },
+            Expression::Default(value, default) => match value.evaluate(lookup)? {
+                None | Some(MValue::Null) => Some(default.clone()),
+                Some(value) => Some(value),
+            },
+        };
+
+        Ok(value)
+    }
+
+    /// The names of every variable the expression reads.
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expression::Variable(name) => vec![name.as_str()],
+            Expression::Literal(_) | Expression::Call(..) => vec![],
+            Expression::Field(target, _)
+            | Expression::Negate(target)
+            | Expression::Filter(target, ..)
+            | Expression::Default(target, _) => target.variables(),
+            Expression::Index(left, right) | Expression::Binary(left, _, right) => {
+                let mut variables = left.variables();
+                variables.extend(right.variables());
+                variables
+            }
+        }
+    }
+
+    /// Returns true if the expression calls a generator.
+    pub fn has_calls(&self) -> bool {
+        match self {
+            Expression::Call(..) => true,
+            Expression::Variable(_) | Expression::Literal(_) => false,
+            Expression::Field(target, _)
+            | Expression::Negate(target)
+            | Expression::Filter(target, ..)
+            | Expression::Default(target, _) => target.has_calls(),
+            Expression::Index(left, right) | Expression::Binary(left, _, right) => {
+                left.has_calls() || right.has_calls()
+            }
+        }
+    }
+
+    /// Returns true if the expression has a default value, so its variables don't need to be
+    /// defined.
+    pub fn has_default(&self) -> bool {
+        matches!(self, Expression::Default(..))
+    }
+
+    /// The variable name if the expression is a plain variable, like `${USER_ID}`.
+    pub fn as_variable(&self) -> Option<&str> {
+        match self {
+            Expression::Variable(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Finds a top level `:-`, which separates the expression from its default value.
+fn split_default(source: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, ':') if depth == 0 && chars.peek().is_some_and(|(_, c)| *c == '-') => {
+                return Some((&source[..i], &source[i + 2..]));
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Default values are read as YAML, so `${PORT:-8080}` defaults to a number.
+fn parse_default(default: &str) -> MValue {
+    match default.trim() {
+        "" => MValue::String(String::new()),
+        default => serde_yaml::from_str::<MValue>(default)
+            .unwrap_or_else(|_| MValue::String(default.to_string())),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, CaptiError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '"' | '\'' => Token::Str(read_string(source, &mut chars)?),
+            c if c.is_alphanumeric() || c == '_' => {
+                let word = read_while(source, &mut chars, |c| c.is_alphanumeric() || c == '_');
+                read_word(source, word, start, &mut chars)?
+            }
+            '.' => single(&mut chars, Token::Dot),
+            '[' => single(&mut chars, Token::LBracket),
+            ']' => single(&mut chars, Token::RBracket),
+            '(' => single(&mut chars, Token::LParen),
+            ')' => single(&mut chars, Token::RParen),
+            '|' => single(&mut chars, Token::Pipe),
+            '+' => single(&mut chars, Token::Operator(Operator::Add)),
+            '-' => single(&mut chars, Token::Operator(Operator::Subtract)),
+            '*' => single(&mut chars, Token::Operator(Operator::Multiply)),
+            '/' => single(&mut chars, Token::Operator(Operator::Divide)),
+            '%' => single(&mut chars, Token::Operator(Operator::Remainder)),
+            other => {
+                return Err(CaptiError::variable_error(format!(
+                    "Unexpected '{}' in expression: {}",
+                    other, source
+                )))
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn single(chars: &mut Peekable<CharIndices>, token: Token) -> Token {
+    chars.next();
+    token
+}
+
+fn read_while<'a>(
+    source: &'a str,
+    chars: &mut Peekable<CharIndices>,
+    predicate: impl Fn(char) -> bool,
+) -> &'a str {
+    let start = chars.peek().map(|(i, _)| *i).unwrap_or(source.len());
+    while chars.peek().is_some_and(|(_, c)| predicate(*c)) {
+        chars.next();
+    }
+    let end = chars.peek().map(|(i, _)| *i).unwrap_or(source.len());
+
+    &source[start..end]
+}
+
+/// Reads a number, a variable name, or a generator or filter call with its arguments.
+fn read_word(
+    source: &str,
+    word: &str,
+    start: usize,
+    chars: &mut Peekable<CharIndices>,
+) -> Result<Token, CaptiError> {
+    if word.chars().all(|c| c.is_ascii_digit()) {
+        let mut lookahead = chars.clone();
+        let is_decimal = lookahead.next().is_some_and(|(_, c)| c == '.')
+            && lookahead.next().is_some_and(|(_, c)| c.is_ascii_digit());
+
+        if !is_decimal {
+            return word
+                .parse::<i64>()
+                .map(|n| Token::Number(Number::from(n)))
+                .map_err(|e| {
+                    CaptiError::variable_error(format!("Invalid number {}: {}", word, e))
+                });
+        }
+
+        chars.next();
+        let fraction = read_while(source, chars, |c| c.is_ascii_digit());
+        let number = format!("{}.{}", word, fraction);
+        return number
+            .parse::<f64>()
+            .map(|n| Token::Number(Number::from(n)))
+            .map_err(|e| CaptiError::variable_error(format!("Invalid number {}: {}", number, e)));
+    }
+
+    if chars.peek().map(|(_, c)| *c) != Some('(') {
+        return Ok(Token::Ident(word.to_string()));
+    }
+
+    chars.next();
+    let args_start = start + word.len() + 1;
+    let mut depth = 1;
+    let mut quote = None;
+
+    for (i, c) in chars.by_ref() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    let args = split_args(&source[args_start..i]);
+                    return Ok(Token::Call(word.to_string(), args));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(CaptiError::variable_error(format!(
+        "Missing ')' for {}( in expression: {}",
+        word, source
+    )))
+}
+
+fn read_string(source: &str, chars: &mut Peekable<CharIndices>) -> Result<String, CaptiError> {
+    let quote = chars.next().map(|(_, c)| c);
+    let mut value = String::new();
+
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c if Some(c) == quote => return Ok(value),
+            c => value.push(c),
+        }
+    }
+
+    Err(CaptiError::variable_error(format!(
+        "Unterminated string in expression: {}",
+        source
+    )))
+}
+
+/// Splits call arguments on top level commas, removing any quotes around each argument.
+/// Arguments are kept as text so values like `+1h` or `[year]-[month]` don't need quoting.
+fn split_args(args: &str) -> Vec<String> {
+    if args.trim().is_empty() {
+        return vec![];
+    }
+
+    let mut result = vec![];
+    let mut current = String::new();
+    let mut quote = None;
+
+    for c in args.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (None, ',') => result.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    result.push(current);
+
+    result
+        .iter()
+        .map(|arg| {
+            let arg = arg.trim();
+            arg.strip_prefix('"')
+                .and_then(|arg| arg.strip_suffix('"'))
+                .or(arg
+                    .strip_prefix('\'')
+                    .and_then(|arg| arg.strip_suffix('\'')))
+                .unwrap_or(arg)
+                .to_string()
+        })
+        .collect()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_pipe(&mut self) -> Result<Expression, CaptiError> {
+        let mut expression = self.parse_additive()?;
+
+        while self.peek() == Some(&Token::Pipe) {
+            self.next();
+            expression = match self.next() {
+                Some(Token::Ident(name)) => Expression::Filter(Box::new(expression), name, vec![]),
+                Some(Token::Call(name, args)) => {
+                    Expression::Filter(Box::new(expression), name, args)
+                }
+                _ => {
+                    return Err(CaptiError::variable_error(
+                        "Expected a filter name after '|'.",
+                    ))
+                }
+            };
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression, CaptiError> {
+        let mut expression = self.parse_term()?;
+
+        while let Some(Token::Operator(operator @ (Operator::Add | Operator::Subtract))) =
+            self.peek().cloned()
+        {
+            self.next();
+            let right = self.parse_term()?;
+            expression = Expression::Binary(Box::new(expression), operator, Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_term(&mut self) -> Result<Expression, CaptiError> {
+        let mut expression = self.parse_unary()?;
+
+        while let Some(Token::Operator(
+            operator @ (Operator::Multiply | Operator::Divide | Operator::Remainder),
+        )) = self.peek().cloned()
+        {
+            self.next();
+            let right = self.parse_unary()?;
+            expression = Expression::Binary(Box::new(expression), operator, Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, CaptiError> {
+        if self.peek() == Some(&Token::Operator(Operator::Subtract)) {
+            self.next();
+            return Ok(Expression::Negate(Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, CaptiError> {
+        let mut expression = self.parse_primary()?;
+
+        loop {
+            expression = match self.peek() {
+                Some(Token::Dot) => {
+                    self.next();
+                    let field = match self.next() {
+                        Some(Token::Ident(field)) => field,
+                        Some(Token::Number(n)) => n.to_string(),
+                        _ => {
+                            return Err(CaptiError::variable_error(
+                                "Expected a field name after '.'.",
+                            ))
+                        }
+                    };
+                    Expression::Field(Box::new(expression), field)
+                }
+                Some(Token::LBracket) => {
+                    self.next();
+                    let index = self.parse_pipe()?;
+                    self.expect(Token::RBracket)?;
+                    Expression::Index(Box::new(expression), Box::new(index))
+                }
+                _ => return Ok(expression),
+            };
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, CaptiError> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(Expression::Variable(name)),
+            Some(Token::Number(n)) => Ok(Expression::Literal(MValue::Number(n))),
+            Some(Token::Str(s)) => Ok(Expression::Literal(MValue::String(s))),
+            Some(Token::Call(name, args)) => Ok(Expression::Call(name, args)),
+            Some(Token::LParen) => {
+                let expression = self.parse_pipe()?;
+                self.expect(Token::RParen)?;
+                Ok(expression)
+            }
+            Some(token) => Err(CaptiError::variable_error(format!(
+                "Unexpected '{}' in expression.",
+                token
+            ))),
+            None => Err(CaptiError::variable_error("Unexpected end of expression.")),
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), CaptiError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(CaptiError::variable_error(format!(
+                "Expected '{}' in expression.",
+                expected
+            ))),
+        }
+    }
+}
+
+fn field_value(target: &MValue, field: &str) -> Option<MValue> {
+    match target {
+        MValue::Mapping(map) => map.get(&MValue::String(field.to_string())).cloned(),
+        MValue::Sequence(seq) => field
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| seq.get(i).cloned()),
+        _ => None,
+    }
+}
+
+fn index_value(target: &MValue, index: &MValue) -> Option<MValue> {
+    match (target, index) {
+        (MValue::Sequence(seq), MValue::Number(n)) => {
+            let i = n.as_i64()?;
+            let i = match i < 0 {
+                true => seq.len().checked_sub(i.unsigned_abs() as usize)?,
+                false => i as usize,
+            };
+            seq.get(i).cloned()
+        }
+        (MValue::Mapping(map), key) => map.get(key).cloned(),
+        _ => None,
+    }
+}
+
+fn arithmetic(left: &MValue, operator: Operator, right: &MValue) -> Result<MValue, CaptiError> {
+    let (l, r) = match (as_number(left), as_number(right)) {
+        (Some(l), Some(r)) => (l, r),
+        _ if operator == Operator::Add => {
+            let left: String = left.clone().into();
+            let right: String = right.clone().into();
+            return Ok(MValue::String(left + &right));
+        }
+        _ => {
+            return Err(CaptiError::variable_error(format!(
+                "Cannot apply '{}' to {} and {}.",
+                operator, left, right
+            )))
+        }
+    };
+
+    if let (Some(l), Some(r)) = (l.as_i64(), r.as_i64()) {
+        let result = match operator {
+            Operator::Add => l.checked_add(r),
+            Operator::Subtract => l.checked_sub(r),
+            Operator::Multiply => l.checked_mul(r),
+            Operator::Divide if r != 0 && l % r == 0 => l.checked_div(r),
+            Operator::Divide => None,
+            Operator::Remainder => l.checked_rem(r),
+        };
+
+        if let Some(result) = result {
+            return Ok(MValue::Number(Number::from(result)));
+        }
+    }
+
+    let (l, r) = (
+        l.as_f64().unwrap_or_default(),
+        r.as_f64().unwrap_or_default(),
+    );
+    if r == 0.0 && matches!(operator, Operator::Divide | Operator::Remainder) {
+        return Err(CaptiError::variable_error(
+            "Division by zero in expression.",
+        ));
+    }
+
+    let result = match operator {
+        Operator::Add => l + r,
+        Operator::Subtract => l - r,
+        Operator::Multiply => l * r,
+        Operator::Divide => l / r,
+        Operator::Remainder => l % r,
+    };
+
+    Ok(MValue::Number(Number::from(result)))
+}
+
+/// Numbers, and strings that contain a number, such as values read from the environment.
+fn as_number(value: &MValue) -> Option<Number> {
+    match value {
+        MValue::Number(n) => Some(n.clone()),
+        MValue::String(s) => s
+            .trim()
+            .parse::<i64>()
+            .map(Number::from)
+            .or(s.trim().parse::<f64>().map(Number::from))
+            .ok(),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Remainder => "%",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Call(name, args) => write!(f, "{}({})", name, args.join(", ")),
+            Token::Dot => write!(f, "."),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Pipe => write!(f, "|"),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn evaluate(source: &str) -> Option<MValue> {
+        let ids = serde_yaml::from_str::<MValue>("[10, 20, 30]").unwrap();
+        let user = serde_yaml::from_str::<MValue>("{ name: Ann, roles: [admin] }").unwrap();
+
+        Expression::parse(source)
+            .unwrap()
+            .evaluate(&mut |name: &str| {
+                Ok(match name {
+                    "ids" => Some(ids.clone()),
+                    "user" => Some(user.clone()),
+                    "price" => Some(MValue::String(String::from("2.5"))),
+                    "token" => Some(MValue::String(String::from("abc"))),
+                    _ => None,
+                })
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_expressions() {
+        assert_eq!(
+            Expression::parse("USER_ID | upper").unwrap(),
+            Expression::Filter(
+                Box::new(Expression::Variable(String::from("USER_ID"))),
+                String::from("upper"),
+                vec![]
+            )
+        );
+
+        assert_eq!(
+            Expression::parse("a + b * 2").unwrap().variables(),
+            vec!["a", "b"]
+        );
+
+        assert!(Expression::parse("a +").is_err());
+        assert!(Expression::parse("a b").is_err());
+    }
+
+    #[test]
+    fn evaluates_field_access_and_indexes() {
+        assert_eq!(evaluate("ids[0]"), Some(MValue::Number(10.into())));
+        assert_eq!(evaluate("ids[-1]"), Some(MValue::Number(30.into())));
+        assert_eq!(evaluate("ids.1"), Some(MValue::Number(20.into())));
+        assert_eq!(
+            evaluate("user.name"),
+            Some(MValue::String(String::from("Ann")))
+        );
+        assert_eq!(
+            evaluate("user.roles[0]"),
+            Some(MValue::String(String::from("admin")))
+        );
+        assert_eq!(
+            evaluate("user['name']"),
+            Some(MValue::String(String::from("Ann")))
+        );
+        assert_eq!(evaluate("user.missing"), None);
+        assert_eq!(evaluate("ids[5]"), None);
+    }
+
+    #[test]
+    fn evaluates_arithmetic() {
+        assert_eq!(evaluate("ids[0] * 2 + 1"), Some(MValue::Number(21.into())));
+        assert_eq!(evaluate("(ids[0] + 2) % 5"), Some(MValue::Number(2.into())));
+        assert_eq!(evaluate("price * 2"), Some(MValue::Number(5.0.into())));
+        assert_eq!(evaluate("-ids[2]"), Some(MValue::Number((-30).into())));
+        assert_eq!(
+            evaluate("token + '-1'"),
+            Some(MValue::String(String::from("abc-1")))
+        );
+    }
+
+    #[test]
+    fn evaluates_defaults() {
+        assert_eq!(evaluate("PORT:-8080"), Some(MValue::Number(8080.into())));
+        assert_eq!(
+            evaluate("HOST:-localhost"),
+            Some(MValue::String(String::from("localhost")))
+        );
+        assert_eq!(
+            evaluate("token:-fallback"),
+            Some(MValue::String(String::from("abc")))
+        );
+        assert_eq!(
+            evaluate("user.nickname:-"),
+            Some(MValue::String(String::new()))
+        );
+    }
+}
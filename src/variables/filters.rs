@@ -0,0 +1,138 @@
+use base64::Engine;
+use serde_yaml::Number;
+
+use crate::{client::signing::uri_encode, errors::CaptiError, m_value::m_value::MValue};
+
+/// Applies a filter like `${TOKEN | base64}` to the value on its left.
+pub fn apply(name: &str, args: &[String], value: MValue) -> Result<MValue, CaptiError> {
+    let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    match (name, args.as_slice()) {
+        ("upper", []) => Ok(MValue::String(as_string(value).to_uppercase())),
+        ("lower", []) => Ok(MValue::String(as_string(value).to_lowercase())),
+        ("trim", []) => Ok(MValue::String(as_string(value).trim().to_string())),
+        ("string", []) => Ok(MValue::String(as_string(value))),
+        ("number", []) => number(value),
+        ("length", []) => length(value),
+        ("first", []) => element(value, |seq| seq.first().cloned()),
+        ("last", []) => element(value, |seq| seq.last().cloned()),
+        ("join", []) => join(value, ","),
+        ("join", [separator]) => join(value, separator),
+        ("base64", []) => Ok(MValue::String(
+            base64::engine::general_purpose::STANDARD.encode(as_string(value)),
+        )),
+        ("base64_decode", []) => base64_decode(value),
+        ("url_encode", []) => Ok(MValue::String(uri_encode(&as_string(value)))),
+        ("json", []) => serde_json::to_string(&value)
+            .map(MValue::String)
+            .map_err(|e| CaptiError::variable_error(format!("Unable to serialize value: {}", e))),
+        (name, args) => Err(CaptiError::variable_error(format!(
+            "Unknown filter or invalid arguments: {}({})",
+            name,
+            args.join(", ")
+        ))),
+    }
+}
+
+fn as_string(value: MValue) -> String {
+    value.into()
+}
+
+fn number(value: MValue) -> Result<MValue, CaptiError> {
+    let text = as_string(value);
+    text.trim()
+        .parse::<i64>()
+        .map(Number::from)
+        .or(text.trim().parse::<f64>().map(Number::from))
+        .map(MValue::Number)
+        .map_err(|_| CaptiError::variable_error(format!("'{}' is not a number.", text)))
+}
+
+fn length(value: MValue) -> Result<MValue, CaptiError> {
+    let length = match value {
+        MValue::Sequence(seq) => seq.len(),
+        MValue::Mapping(map) => map.len(),
+        other => as_string(other).chars().count(),
+    };
+
+    Ok(MValue::Number(Number::from(length)))
+}
+
+fn element(
+    value: MValue,
+    select: impl Fn(&[MValue]) -> Option<MValue>,
+) -> Result<MValue, CaptiError> {
+    match value {
+        MValue::Sequence(seq) => Ok(select(seq.as_slice()).unwrap_or(MValue::Null)),
+        other => Err(CaptiError::variable_error(format!(
+            "Expected a sequence, found {}.",
+            other
+        ))),
+    }
+}
+
+fn join(value: MValue, separator: &str) -> Result<MValue, CaptiError> {
+    match value {
+        MValue::Sequence(seq) => Ok(MValue::String(
+            seq.iter()
+                .map(|value| as_string(value.clone()))
+                .collect::<Vec<String>>()
+                .join(separator),
+        )),
+        other => Err(CaptiError::variable_error(format!(
+            "Expected a sequence to join, found {}.",
+            other
+        ))),
+    }
+}
+
+fn base64_decode(value: MValue) -> Result<MValue, CaptiError> {
+    let text = as_string(value);
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(text.trim())
+        .map_err(|e| CaptiError::variable_error(format!("Invalid base64 '{}': {}", text, e)))?;
+
+    String::from_utf8(bytes)
+        .map(MValue::String)
+        .map_err(|_| CaptiError::variable_error("Decoded base64 value is not valid UTF-8."))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn applies_filters() {
+        let value = MValue::String(String::from("user:pass"));
+        assert_eq!(
+            apply("base64", &[], value.clone()).unwrap(),
+            MValue::String(String::from("dXNlcjpwYXNz"))
+        );
+        assert_eq!(
+            apply(
+                "base64_decode",
+                &[],
+                MValue::String(String::from("dXNlcjpwYXNz"))
+            )
+            .unwrap(),
+            value
+        );
+        assert_eq!(
+            apply("upper", &[], value.clone()).unwrap(),
+            MValue::String(String::from("USER:PASS"))
+        );
+        assert_eq!(
+            apply("url_encode", &[], value).unwrap(),
+            MValue::String(String::from("user%3Apass"))
+        );
+
+        let seq = serde_yaml::from_str::<MValue>("[a, b, c]").unwrap();
+        assert_eq!(
+            apply("join", &[String::from("-")], seq.clone()).unwrap(),
+            MValue::String(String::from("a-b-c"))
+        );
+        assert_eq!(apply("length", &[], seq).unwrap(), MValue::Number(3.into()));
+
+        assert!(apply("missing", &[], MValue::Null).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use rand::{distributions::Alphanumeric, Rng};
+use serde_yaml::Number;
+use time::{
+    format_description::{self, well_known},
+    Duration, OffsetDateTime,
+};
+
+use crate::{errors::CaptiError, m_value::m_value::MValue};
+
+/// Counters for `sequence(name)`, shared by every suite for the duration of the run.
+static SEQUENCES: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(Default::default);
+
+/// Generates a fresh value for a generator call such as `${random_int(1, 100)}`.
+pub fn call(name: &str, args: &[String]) -> Result<MValue, CaptiError> {
+    let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    match (name, args.as_slice()) {
+        ("uuid", []) => Ok(MValue::String(uuid())),
+        ("random_int", args) => random_int(args),
+        ("random_string", args) => random_string(args),
+        ("random_email", []) => Ok(MValue::String(random_email("test.com"))),
+        ("random_email", [domain]) => Ok(MValue::String(random_email(domain))),
+        ("timestamp", args) => timestamp(args),
+        ("sequence", []) => Ok(sequence("default")),
+        ("sequence", [name]) => Ok(sequence(name)),
+        (name, args) => Err(CaptiError::variable_error(format!(
+            "Unknown generator or invalid arguments: {}({})",
+            name,
+            args.join(", ")
+        ))),
+    }
+}
+
+fn uuid() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    // Version 4, variant 1
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn random_int(args: &[&str]) -> Result<MValue, CaptiError> {
+    let (min, max) = match args {
+        [] => (0, 1_000_000),
+        [max] => (0, parse_number(max)?),
+        [min, max] => (parse_number(min)?, parse_number(max)?),
+        _ => return Err(invalid_args("random_int", "random_int(min, max)")),
+    };
+
+    if min > max {
+        return Err(CaptiError::variable_error(format!(
+            "Invalid range for random_int: {} is greater than {}.",
+            min, max
+        )));
+    }
+
+    let value = rand::thread_rng().gen_range(min..=max);
+    Ok(MValue::Number(Number::from(value)))
+}
+
+fn random_string(args: &[&str]) -> Result<MValue, CaptiError> {
+    let (length, charset) = match args {
+        [] => (16, "alphanumeric"),
+        [length] => (parse_length(length)?, "alphanumeric"),
+        [length, charset] => (parse_length(length)?, *charset),
+        _ => {
+            return Err(invalid_args(
+                "random_string",
+                "random_string(length, charset)",
+            ))
+        }
+    };
+
+    let chars: &[u8] = match charset {
+        "alphanumeric" => {
+            let mut rng = rand::thread_rng();
+            let value = (0..length)
+                .map(|_| rng.sample(Alphanumeric) as char)
+                .collect::<String>();
+            return Ok(MValue::String(value));
+        }
+        "alpha" => b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ",
+        "lower" => b"abcdefghijklmnopqrstuvwxyz",
+        "upper" => b"ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+        "numeric" => b"0123456789",
+        "hex" => b"0123456789abcdef",
+        other => {
+            return Err(CaptiError::variable_error(format!(
+                "Unknown charset for random_string: {}\nMust be one of alphanumeric, alpha, lower, upper, numeric, or hex.",
+                other
+            )))
+        }
+    };
+
+    let mut rng = rand::thread_rng();
+    let value = (0..length)
+        .map(|_| chars[rng.gen_range(0..chars.len())] as char)
+        .collect::<String>();
+
+    Ok(MValue::String(value))
+}
+
+fn random_email(domain: &str) -> String {
+    let mut rng = rand::thread_rng();
+    let user = (0..12)
+        .map(|_| rng.sample(Alphanumeric) as char)
+        .collect::<String>()
+        .to_lowercase();
+
+    format!("user-{}@{}", user, domain)
+}
+
+fn timestamp(args: &[&str]) -> Result<MValue, CaptiError> {
+    let (format, offset) = match args {
+        [] => ("unix", None),
+        [format] => (*format, None),
+        [format, offset] => (*format, Some(*offset)),
+        _ => return Err(invalid_args("timestamp", "timestamp(format, offset)")),
+    };
+
+    let mut now = OffsetDateTime::now_utc();
+    if let Some(offset) = offset {
+        now += parse_offset(offset)?;
+    }
+
+    let formatted = match format {
+        "unix" => return Ok(MValue::Number(Number::from(now.unix_timestamp()))),
+        "unix_ms" => {
+            let millis = now.unix_timestamp_nanos() / 1_000_000;
+            return Ok(MValue::Number(Number::from(millis as i64)));
+        }
+        "iso8601" | "rfc3339" => now.format(&well_known::Rfc3339),
+        "rfc2822" => now.format(&well_known::Rfc2822),
+        "date" => now.format(
+            &format_description::parse("[year]-[month]-[day]").map_err(|e| {
+                CaptiError::variable_error(format!("Invalid timestamp format: {}", e))
+            })?,
+        ),
+        custom => {
+            let description = format_description::parse(custom).map_err(|e| {
+                CaptiError::variable_error(format!("Invalid timestamp format '{}': {}", custom, e))
+            })?;
+            now.format(&description)
+        }
+    }
+    .map_err(|e| CaptiError::variable_error(format!("Unable to format timestamp: {}", e)))?;
+
+    Ok(MValue::String(formatted))
+}
+
+/// Parses an offset like `+1h`, `-30m`, or `7d`.
+fn parse_offset(offset: &str) -> Result<Duration, CaptiError> {
+    let invalid = || {
+        CaptiError::variable_error(format!(
+            "Invalid timestamp offset: {}\nOffsets must be a number followed by s, m, h, or d, like +1h or -30m.",
+            offset
+        ))
+    };
+
+    let offset = offset.trim();
+    let (sign, offset) = match offset.strip_prefix('-') {
+        Some(offset) => (-1, offset),
+        None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+
+    let unit = offset.chars().last().ok_or_else(invalid)?;
+    let amount = offset[..offset.len() - unit.len_utf8()]
+        .parse::<i64>()
+        .map_err(|_| invalid())?
+        * sign;
+
+    match unit {
+        's' => Ok(Duration::seconds(amount)),
+        'm' => Ok(Duration::minutes(amount)),
+        'h' => Ok(Duration::hours(amount)),
+        'd' => Ok(Duration::days(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+fn sequence(name: &str) -> MValue {
+    let mut sequences = match SEQUENCES.lock() {
+        Ok(sequences) => sequences,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    let counter = sequences.entry(name.to_string()).or_insert(0);
+    *counter += 1;
+
+    MValue::Number(Number::from(*counter))
+}
+
+fn parse_number(value: &str) -> Result<i64, CaptiError> {
+    value.parse::<i64>().map_err(|_| {
+        CaptiError::variable_error(format!("Expected a whole number, found '{}'.", value))
+    })
+}
+
+/// Parses the length of a random string, which can't be negative.
+fn parse_length(value: &str) -> Result<usize, CaptiError> {
+    value
+        .parse::<usize>()
+        .map_err(|_| invalid_args("random_string", "random_string(length, charset)"))
+}
+
+fn invalid_args(name: &str, usage: &str) -> CaptiError {
+    CaptiError::variable_error(format!("Invalid arguments for {}.\nUsage: {}", name, usage))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generate(name: &str, args: &[&str]) -> Result<MValue, CaptiError> {
+        let args = args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>();
+        call(name, &args)
+    }
+
+    #[test]
+    fn generates_values() {
+        let uuid = generate("uuid", &[]).unwrap().to_string();
+        assert_eq!(uuid.trim_matches('"').len(), 36);
+
+        match generate("random_int", &["5", "5"]).unwrap() {
+            MValue::Number(n) => assert_eq!(n, Number::from(5)),
+            other => panic!("Expected number, found {}", other),
+        }
+
+        match generate("random_string", &["8", "numeric"]).unwrap() {
+            MValue::String(s) => assert!(s.len() == 8 && s.chars().all(|c| c.is_ascii_digit())),
+            other => panic!("Expected string, found {}", other),
+        }
+
+        match generate("random_email", &["example.com"]).unwrap() {
+            MValue::String(s) => assert!(s.starts_with("user-") && s.ends_with("@example.com")),
+            other => panic!("Expected string, found {}", other),
+        }
+
+        assert!(generate("unknown", &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_string_lengths() {
+        assert!(generate("random_string", &["-1"]).is_err());
+        assert!(generate("random_string", &["2.5", "hex"]).is_err());
+        assert_eq!(
+            generate("random_string", &["0"]).unwrap(),
+            MValue::String("".into())
+        );
+    }
+
+    #[test]
+    fn increments_sequences() {
+        assert_eq!(
+            generate("sequence", &["test"]).unwrap(),
+            MValue::Number(1.into())
+        );
+        assert_eq!(
+            generate("sequence", &["test"]).unwrap(),
+            MValue::Number(2.into())
+        );
+        assert_eq!(
+            generate("sequence", &["other"]).unwrap(),
+            MValue::Number(1.into())
+        );
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("+1h").unwrap(), Duration::hours(1));
+        assert_eq!(parse_offset("-30m").unwrap(), Duration::minutes(-30));
+        assert_eq!(parse_offset("7d").unwrap(), Duration::days(7));
+        assert!(parse_offset("1w").is_err());
+    }
+}
@@ -1,6 +1,13 @@
+mod expression;
+mod filters;
+mod generators;
+pub mod references;
+pub mod secrets;
 mod self_reference_context;
+pub mod source;
 mod var_regex;
 pub mod variable_map;
 pub mod variables;
 
+pub use references::{VariableReference, VariableReferences};
 pub use variables::SuiteVariables;
@@ -0,0 +1,96 @@
+use once_cell::sync::Lazy;
+
+use super::{expression::Expression, var_regex::VarRegex};
+
+static VAR_REGEX: Lazy<VarRegex> = Lazy::new(VarRegex::default);
+
+/// A single `${VARIABLE}` reference found in a test definition, along with the field where the
+/// reference was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableReference {
+    pub name: String,
+    pub field: String,
+    /// True if the reference has a default value, like `${PORT:-8080}`.
+    pub optional: bool,
+}
+
+impl VariableReference {
+    pub fn new(name: impl Into<String>, field: impl Into<String>) -> Self {
+        VariableReference {
+            name: name.into(),
+            field: field.into(),
+            optional: false,
+        }
+    }
+
+    /// Finds every variable referenced in the provided string, including variables used inside
+    /// expressions like `${ids[0]}`. Generator calls are not references, since they never need
+    /// to be defined.
+    pub fn find_all(value: &str, field: &str) -> Vec<VariableReference> {
+        VAR_REGEX
+            .captures_iter(value)
+            .filter_map(|captures| Expression::parse(&captures[1]).ok())
+            .flat_map(|expression| {
+                expression
+                    .variables()
+                    .into_iter()
+                    .map(|name| VariableReference {
+                        optional: expression.has_default(),
+                        ..VariableReference::new(name, field)
+                    })
+                    .collect::<Vec<VariableReference>>()
+            })
+            .collect()
+    }
+}
+
+/// The VariableReferences trait allows a value to report which variables it references without
+/// resolving them, so that variable usage can be analyzed before tests run.
+pub trait VariableReferences {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference>;
+}
+
+impl<T> VariableReferences for Option<T>
+where
+    T: VariableReferences,
+{
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        match self {
+            Some(value) => value.variable_references(field),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl VariableReferences for String {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        VariableReference::find_all(self, field)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn marks_references_with_defaults_optional() {
+        let references = VariableReference::find_all("${HOST:-localhost}:${PORT}", "url");
+
+        assert!(references[0].optional);
+        assert!(!references[1].optional);
+    }
+
+    #[test]
+    fn finds_all_references_in_string() {
+        let references =
+            VariableReference::find_all("${BASE_URL}/users/${USER_ID | upper}?n=${uuid()}", "url");
+
+        assert_eq!(
+            references,
+            vec![
+                VariableReference::new("BASE_URL", "url"),
+                VariableReference::new("USER_ID", "url"),
+            ]
+        );
+    }
+}
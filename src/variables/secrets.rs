@@ -0,0 +1,87 @@
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+/// Replaces secret values in printed output.
+pub const MASK: &str = "******";
+
+/// Shorter values are not masked, since they would match too much unrelated output.
+const MIN_SECRET_LENGTH: usize = 4;
+
+/// Every secret value seen during the run, longest first so a secret that contains another is
+/// masked whole.
+static SECRETS: Lazy<RwLock<Vec<String>>> = Lazy::new(Default::default);
+
+/// Parts of environment variable names that mark their values as credentials.
+const CREDENTIAL_NAMES: [&str; 4] = ["TOKEN", "SECRET", "PASSWORD", "KEY"];
+
+/// Returns true for environment variable names like `API_KEY` or `DB_PASSWORD`, whose values are
+/// masked in output.
+pub fn is_credential_name(name: &str) -> bool {
+    let name = name.to_uppercase();
+    CREDENTIAL_NAMES.iter().any(|part| name.contains(part))
+}
+
+/// Records a value that must not appear in any output.
+pub fn register(value: impl Into<String>) {
+    let value = value.into();
+    if value.chars().count() < MIN_SECRET_LENGTH {
+        return;
+    }
+
+    let mut secrets = match SECRETS.write() {
+        Ok(secrets) => secrets,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    if !secrets.contains(&value) {
+        secrets.push(value);
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    }
+}
+
+/// Records an `Authorization` header value, along with the credentials that follow its scheme.
+pub fn register_credentials(value: &str) {
+    register(value);
+    if let Some((_, credentials)) = value.split_once(' ') {
+        register(credentials.trim());
+    }
+}
+
+/// Masks every registered secret in the text.
+pub fn redact(text: &str) -> String {
+    let secrets = match SECRETS.read() {
+        Ok(secrets) => secrets,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    secrets
+        .iter()
+        .fold(text.to_string(), |text, secret| text.replace(secret, MASK))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redacts_registered_secrets() {
+        register("s3cr3t-token");
+        register("s3cr3t-token-extended");
+        register("abc");
+
+        assert_eq!(
+            redact("Bearer s3cr3t-token, s3cr3t-token-extended, abc"),
+            format!("Bearer {}, {}, abc", MASK, MASK)
+        );
+    }
+
+    #[test]
+    fn finds_credential_names() {
+        assert!(is_credential_name("API_KEY"));
+        assert!(is_credential_name("github_token"));
+        assert!(is_credential_name("DB_PASSWORD"));
+        assert!(!is_credential_name("PORT"));
+        assert!(!is_credential_name("BASE_URL"));
+    }
+}
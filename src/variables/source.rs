@@ -0,0 +1,50 @@
+use std::{fmt, path::PathBuf};
+
+use crate::m_value::m_value::MValue;
+
+/// Where the value of a variable comes from, listed from highest precedence to lowest.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariableSource {
+    /// A `--var KEY=VALUE` argument.
+    Argument,
+    /// A file passed with `--var-file`.
+    VarFile(PathBuf),
+    /// The variables of the environment selected with `--env`.
+    Environment(String),
+    /// The `define` block of the named test.
+    Define(String),
+    /// The suite's `variables`.
+    Suite,
+    /// Exported by a dependency or extracted by a test while the suite runs.
+    Runtime,
+    /// The shell environment Capti was started from.
+    Shell,
+    /// An env file from the config or the selected environment.
+    EnvFile(PathBuf),
+    Undefined,
+}
+
+impl fmt::Display for VariableSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariableSource::Argument => write!(f, "--var"),
+            VariableSource::VarFile(path) => write!(f, "--var-file {}", path.display()),
+            VariableSource::Environment(name) => write!(f, "environment '{}'", name),
+            VariableSource::Define(test) => write!(f, "define in '{}'", test),
+            VariableSource::Suite => write!(f, "suite variables"),
+            VariableSource::Runtime => write!(f, "set while running"),
+            VariableSource::Shell => write!(f, "shell environment"),
+            VariableSource::EnvFile(path) => write!(f, "env file {}", path.display()),
+            VariableSource::Undefined => write!(f, "not defined"),
+        }
+    }
+}
+
+/// A variable's final value and where it comes from, as shown by `--print-vars`. Variables that
+/// are only set while the suite runs have no value yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedVariable {
+    pub name: String,
+    pub value: Option<MValue>,
+    pub source: VariableSource,
+}
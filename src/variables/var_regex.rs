@@ -2,8 +2,11 @@ use std::ops::Deref;
 
 use regex::Regex;
 
-// Matches continuous ${words} wrapped like ${this}
-pub static VARIABLE_MATCHER: &str = r"\$\{(\w+)\}";
+// Matches ${expressions} wrapped like ${this}, which are parsed by `Expression`
+pub static VARIABLE_MATCHER: &str = r"\$\{([^{}]+)\}";
+
+// Matches only plain ${variables} in extractors, so other ${...} text is matched literally
+pub static EXTRACT_MATCHER: &str = r"\$\{(\w+)\}";
 
 #[derive(Debug, Clone)]
 pub struct VarRegex(Regex);
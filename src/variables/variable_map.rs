@@ -4,7 +4,7 @@ use std::{
 };
 
 use colored::Colorize;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 use regex::{escape, Captures, Regex};
 
@@ -13,31 +13,98 @@ use crate::{
 };
 
 use super::{
+    expression::Expression,
+    references::{VariableReference, VariableReferences},
+    secrets,
     self_reference_context::SelfReferenceContext,
-    var_regex::{VarRegex, VARIABLE_MATCHER},
+    var_regex::{VarRegex, EXTRACT_MATCHER, VARIABLE_MATCHER},
     SuiteVariables,
 };
 
 #[derive(Debug, Clone, PartialEq, Default, Deserialize)]
-#[serde(transparent)]
+#[serde(from = "HashMap<String, MValue>")]
 pub struct VariableMap {
     map: HashMap<String, MValue>,
-    #[serde(skip)]
+    /// Variables defined with `secret: true`, whose values are masked in output.
+    secrets: HashSet<String>,
     var_regex: VarRegex,
 }
 
-impl<'a> VariableMap {
-    fn list_variables(&'a self, value: &'a MValue) -> Vec<&'a str> {
+impl From<HashMap<String, MValue>> for VariableMap {
+    fn from(definitions: HashMap<String, MValue>) -> Self {
+        let mut variables = VariableMap::new();
+        for (key, value) in definitions {
+            variables.insert(key, value);
+        }
+
+        variables
+    }
+}
+
+/// Deserializes the `variables` of a suite or config file, or a test's `define`, where a variable
+/// can be marked as secret.
+pub fn deserialize_definitions<'de, D>(deserializer: D) -> Result<VariableMap, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let definitions = HashMap::<String, MValue>::deserialize(deserializer)?;
+    let mut variables = VariableMap::new();
+
+    for (key, value) in definitions {
+        match secret_definition(&value) {
+            Some((value, is_secret)) => {
+                if is_secret {
+                    variables.secrets.insert(key.clone());
+                    if let MValue::String(value) = &value {
+                        if !value.contains("${") {
+                            secrets::register(value);
+                        }
+                    }
+                }
+                variables.insert(key, value);
+            }
+            None => variables.insert(key, value),
+        }
+    }
+
+    Ok(variables)
+}
+
+/// Reads a variable defined as a mapping of `value` and `secret`, like:
+/// ```yaml
+/// API_KEY:
+///   value: ${VENDOR_API_KEY}
+///   secret: true
+/// ```
+fn secret_definition(value: &MValue) -> Option<(MValue, bool)> {
+    let MValue::Mapping(map) = value else {
+        return None;
+    };
+
+    let secret = map.get(&MValue::String(String::from("secret")));
+    let value = map.get(&MValue::String(String::from("value")));
+
+    match (map.len(), value, secret) {
+        (2, Some(value), Some(MValue::Bool(secret))) => Some((value.clone(), *secret)),
+        _ => None,
+    }
+}
+
+impl VariableMap {
+    /// The names of the defined variables referenced by a value.
+    fn list_variables(&self, value: &MValue) -> Vec<String> {
         let mut vars = vec![];
         match value {
             MValue::String(value) => {
-                if let Some(captures) = self.var_regex.captures(value) {
-                    let var_name = match captures.get(1) {
-                        Some(name) => name.as_str(),
-                        None => return vars,
-                    };
-                    if self.map.contains_key(var_name) {
-                        vars.push(var_name);
+                for captures in self.var_regex.captures_iter(value) {
+                    if let Ok(expression) = Expression::parse(&captures[1]) {
+                        vars.extend(
+                            expression
+                                .variables()
+                                .into_iter()
+                                .filter(|name| self.map.contains_key(*name))
+                                .map(String::from),
+                        );
                     }
                 }
             }
@@ -64,6 +131,7 @@ impl VariableMap {
     pub fn new() -> Self {
         VariableMap {
             map: HashMap::new(),
+            secrets: HashSet::new(),
             var_regex: VarRegex::default(),
         }
     }
@@ -72,6 +140,26 @@ impl VariableMap {
         self.map.insert(key.into(), value.into());
     }
 
+    /// Inserts every variable from the other map, replacing any existing values.
+    pub fn extend(&mut self, other: &VariableMap) {
+        for (key, value) in other.map.iter() {
+            self.insert(key.clone(), value.clone());
+        }
+        self.secrets.extend(other.secrets.iter().cloned());
+    }
+
+    /// Inserts a variable whose value is masked in output once it is resolved.
+    pub fn insert_secret(&mut self, key: impl Into<String>, value: impl Into<MValue>) {
+        let key = key.into();
+        self.secrets.insert(key.clone());
+        self.insert(key, value);
+    }
+
+    /// Returns true if the variable was defined with `secret: true`.
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.secrets.contains(key)
+    }
+
     pub fn insert_if_absent(&mut self, key: impl Into<String>, value: impl Into<MValue>) {
         let key = key.into();
         if !self.map.contains_key(&key) {
@@ -97,8 +185,13 @@ impl VariableMap {
             return Some(value.clone());
         }
 
+        // Shell environment variables named like credentials are masked. Values from env files
+        // are masked when they are loaded.
         let env_result = std::env::var(key);
         if let Ok(env_value) = env_result {
+            if secrets::is_credential_name(key) {
+                secrets::register(&env_value);
+            }
             return Some(env_value.into());
         }
 
@@ -109,40 +202,30 @@ impl VariableMap {
         None
     }
 
-    fn has_variables(&mut self, value: &str) -> bool {
-        let var_regex = self.var_regex.clone();
-
-        // Variables can still exist in the string despite them missing from the map
-        // This makes sure the variables not only exist but also exist in the map
-        var_regex.captures(value).is_some_and(|c| {
-            let var_name = match c.get(1) {
-                Some(name) => name.as_str(),
-                None => return false,
-            };
-
-            self.get(var_name).is_some()
-        })
+    fn has_variables(&self, value: &str) -> bool {
+        self.var_regex.is_match(value)
     }
 
     fn contains_reference_cycle(&self, key: &str) -> SelfReferenceContext {
         let mut queue = VecDeque::new();
-        queue.push_back((key, Vec::new()));
+        queue.push_back((key.to_string(), Vec::new()));
 
         let mut visited = HashSet::new();
 
         while let Some((current, path)) = queue.pop_front() {
-            let mut path = path;
+            let mut path: Vec<String> = path;
 
             if visited.contains(&current) {
-                let mut context = SelfReferenceContext::from_path(path, current);
+                let path = path.iter().map(String::as_str).collect();
+                let mut context = SelfReferenceContext::from_path(path, &current);
                 context.flag();
 
                 return context;
             }
 
-            visited.insert(current);
+            visited.insert(current.clone());
 
-            let values = match self.map.get(current) {
+            let values = match self.map.get(&current) {
                 Some(v) => self.list_variables(v),
                 None => vec![],
             };
@@ -176,41 +259,114 @@ impl VariableMap {
 
     fn replace_string_value(&mut self, value: &str) -> Result<MValue, CaptiError> {
         let var_regex = self.var_regex.clone();
+        let mut result = String::new();
+        let mut last_end = 0;
 
-        let result = var_regex.replace_all(value, |captures: &Captures| {
-            if let Some(replacement_val) = self.get(&captures[1]) {
-                replacement_val.into()
-            } else {
-                captures[0].to_string()
+        for captures in var_regex.captures_iter(value) {
+            let Some(whole) = captures.get(0) else {
+                continue;
+            };
+
+            result.push_str(&value[last_end..whole.start()]);
+            match self.evaluate(&captures[1])? {
+                Some(replacement_val) => result.push_str(&Into::<String>::into(replacement_val)),
+                None => result.push_str(whole.as_str()),
             }
-        });
 
-        let mut result = MValue::String(result.to_string());
+            last_end = whole.end();
+        }
 
-        result.populate_variables(self)?;
+        result.push_str(&value[last_end..]);
 
-        Ok(result)
+        Ok(MValue::String(result))
     }
 
     fn replace_whole_value(&mut self, value: &str) -> Result<MValue, CaptiError> {
-        let mut result = match self.var_regex.captures(value) {
-            Some(captures) => match captures.get(1) {
-                Some(var_name) => self.get(var_name.as_str()).unwrap_or(MValue::Null),
-                None => MValue::Null,
-            },
-            None => MValue::Null,
+        let captures = match self.var_regex.clone().captures(value) {
+            Some(captures) => captures[1].to_string(),
+            None => return Ok(MValue::String(value.to_string())),
+        };
+
+        // Whole values keep their type, so `${COUNT}` can be a number or a mapping
+        Ok(self
+            .evaluate(&captures)?
+            .unwrap_or(MValue::String(value.to_string())))
+    }
+
+    /// Evaluates the text of a `${...}` expression, returning `None` if anything it references is
+    /// not defined. Text that isn't a valid expression, like a template literal in a request
+    /// body, is left as it is.
+    fn evaluate(&mut self, source: &str) -> Result<Option<MValue>, CaptiError> {
+        let expression = match Expression::parse(source) {
+            Ok(expression) => expression,
+            Err(_) => return Ok(None),
         };
 
-        result.populate_variables(self)?;
+        expression.evaluate(&mut |name: &str| self.resolve(name))
+    }
+
+    /// Looks up a variable, with any variables in its value already replaced.
+    fn resolve(&mut self, name: &str) -> Result<Option<MValue>, CaptiError> {
+        match self.get(name) {
+            Some(mut value) => {
+                value.populate_variables(self)?;
+                if self.secrets.contains(name) {
+                    secrets::register(value.clone());
+                }
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces generator calls in the variable definitions with generated values, so every use
+    /// of a variable like `EMAIL: ${random_email()}` within the suite sees the same value.
+    pub fn resolve_generators(&mut self) {
+        let var_regex = self.var_regex.clone();
+        let total_regex = Regex::new(&format!("^{}$", VARIABLE_MATCHER)).ok();
+
+        for value in self.map.values_mut() {
+            let MValue::String(definition) = value else {
+                continue;
+            };
+
+            let whole = total_regex
+                .as_ref()
+                .and_then(|r| r.captures(definition))
+                .map(|captures| captures[1].to_string());
+
+            if let Some(generated) = whole.as_deref().and_then(generated) {
+                *value = generated;
+                continue;
+            }
+
+            let resolved = var_regex.replace_all(definition, |captures: &Captures| {
+                generated(&captures[1])
+                    .map(|value| value.into())
+                    .unwrap_or(captures[0].to_string())
+            });
+            *definition = resolved.to_string();
+        }
+    }
+
+    /// Returns the variable name if the extractor is a single variable like `${USER}`, which can
+    /// extract a value of any type, such as a mapping whose fields are read with `${USER.name}`.
+    pub fn whole_variable(&self, extractor: &str) -> Option<String> {
+        let total_regex = Regex::new(&format!("^{}$", VARIABLE_MATCHER)).ok()?;
+        let captures = total_regex.captures(extractor)?;
 
-        Ok(result)
+        Expression::parse(&captures[1])
+            .ok()?
+            .as_variable()
+            .map(String::from)
     }
 
     pub fn extract_variables(&mut self, extractor: &str, actual: &str) -> Result<(), CaptiError> {
         let mut regex_pattern = String::from("^");
         let mut last_end = 0;
 
-        for cap in self.var_regex.captures_iter(extractor) {
+        let extract_regex = Regex::new(EXTRACT_MATCHER)?;
+        for cap in extract_regex.captures_iter(extractor) {
             let start = match cap.get(0) {
                 Some(start) => start.start(),
                 None => continue,
@@ -221,10 +377,7 @@ impl VariableMap {
                 None => continue,
             };
 
-            let variable_name = match cap.get(1) {
-                Some(name) => name.as_str(),
-                None => continue,
-            };
+            let variable_name = &cap[1];
 
             regex_pattern.push_str(&escape(&extractor[last_end..start]));
             regex_pattern.push_str(&format!("(?P<{}>.+?)", variable_name));
@@ -250,6 +403,32 @@ impl VariableMap {
     }
 }
 
+/// Evaluates an expression that only calls generators, like `${uuid()}` or
+/// `${random_string(8) | upper}`.
+fn generated(source: &str) -> Option<MValue> {
+    let expression = Expression::parse(source).ok()?;
+    if !expression.has_calls() || !expression.variables().is_empty() {
+        return None;
+    }
+
+    match expression.evaluate(&mut |_: &str| Ok(None)) {
+        Ok(value) => value,
+        Err(e) => {
+            progress_println!("{}: {}", "ERROR".red(), e);
+            None
+        }
+    }
+}
+
+impl VariableReferences for VariableMap {
+    fn variable_references(&self, field: &str) -> Vec<VariableReference> {
+        self.map
+            .iter()
+            .flat_map(|(key, value)| value.variable_references(&format!("{}.{}", field, key)))
+            .collect()
+    }
+}
+
 impl Deref for VariableMap {
     type Target = HashMap<String, MValue>;
     fn deref(&self) -> &Self::Target {
@@ -352,4 +531,116 @@ mod test {
         assert_eq!(variables["ABC"], MValue::String("333".into()));
         assert_eq!(variables["DEF"], MValue::String("1111".into()));
     }
+
+    #[test]
+    fn replaces_generator_calls() {
+        let mut variables = VariableMap::new();
+
+        let result = variables.replace_variables("${random_int(7, 7)}").unwrap();
+        assert_eq!(result, MValue::Number(7.into()));
+
+        let result = variables
+            .replace_variables("id-${random_string(4, numeric)}")
+            .unwrap();
+        match result {
+            MValue::String(s) => assert!(s.starts_with("id-") && s.len() == 7),
+            other => panic!("Expected string, found {}", other),
+        }
+    }
+
+    #[test]
+    fn resolves_generators_once_per_suite() {
+        let mut variables = VariableMap::new();
+        variables.insert("ID", "${uuid()}");
+        variables.insert("EMAIL", "user+${random_string(6)}@test.com");
+        variables.resolve_generators();
+
+        let first = variables.replace_variables("${ID} ${EMAIL}").unwrap();
+        let second = variables.replace_variables("${ID} ${EMAIL}").unwrap();
+
+        assert_eq!(first, second);
+        assert!(!Into::<String>::into(first).contains("${"));
+    }
+
+    #[test]
+    fn matches_other_expressions_literally_when_extracting() {
+        let mut variables = VariableMap::new();
+        variables
+            .extract_variables("${1+1} is ${SUM}, ${a.b}", "${1+1} is 2, ${a.b}")
+            .unwrap();
+
+        assert_eq!(variables.get("SUM"), Some(MValue::String("2".into())));
+        assert!(variables.extract_variables("${uuid()}", "abc").is_ok());
+        assert_eq!(variables.get("uuid"), None);
+    }
+
+    #[test]
+    fn replaces_expressions() {
+        let mut variables = VariableMap::new();
+        variables.insert("USER_ID", "abc");
+        variables.insert("BASE_URL", "http://localhost");
+        variables.insert("ids", serde_yaml::from_str::<MValue>("[7, 8]").unwrap());
+        variables.insert("PRICE", "${ids[1] * 2}");
+
+        let result = variables
+            .replace_variables("${BASE_URL}/users/${ids[0]}/${USER_ID | upper}")
+            .unwrap();
+        assert_eq!(
+            result,
+            MValue::String("http://localhost/users/7/ABC".into())
+        );
+
+        let result = variables.replace_variables("${PRICE}").unwrap();
+        assert_eq!(result, MValue::Number(16.into()));
+
+        let result = variables.replace_variables("${MISSING:-none}").unwrap();
+        assert_eq!(result, MValue::String("none".into()));
+    }
+
+    #[test]
+    fn leaves_unresolved_expressions() {
+        let mut variables = VariableMap::new();
+
+        let value = "const a = `${ missing.field }` and ${not valid!}";
+        let result = variables.replace_variables(value).unwrap();
+
+        assert_eq!(result, MValue::String(value.into()));
+    }
+
+    #[test]
+    fn deserializes_secret_variables() {
+        let yaml = r#"
+        BASE_URL: http://localhost:3000
+        API_KEY:
+          value: k3y-from-suite
+          secret: true
+        "#;
+
+        let mut variables =
+            deserialize_definitions(serde_yaml::Deserializer::from_str(yaml)).unwrap();
+
+        assert!(variables.secrets.contains("API_KEY"));
+        assert_eq!(
+            variables.replace_variables("${API_KEY}").unwrap(),
+            MValue::String("k3y-from-suite".into())
+        );
+        assert_eq!(
+            secrets::redact("key=k3y-from-suite"),
+            format!("key={}", secrets::MASK)
+        );
+    }
+
+    #[test]
+    fn keeps_secret_mappings_outside_of_definitions() {
+        let yaml = r#"
+        RECORD:
+          value: 12
+          secret: false
+        "#;
+
+        let variables = serde_yaml::from_str::<VariableMap>(yaml).unwrap();
+
+        assert!(variables.secrets.is_empty());
+        assert!(matches!(variables.get("RECORD"), Some(MValue::Mapping(_))));
+    }
 }